@@ -0,0 +1,523 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    combat::{roll_attack, AttackProfile, Combat, CombatResult, CombatRules, DamageType, HitOutcome, Resistances},
+    components::{BlocksSight, Enemy, Energy, LevelEntity, Player, PlayersTurn, Position, RenderLayer, TakingTurn},
+    dice::Dice,
+    fov::has_line_of_sight,
+    grid::{ATLAS_CELL_SIZE, TILE_SIZE},
+    hud::MessageLog,
+    map::DungeonMap,
+    projectile::{flight_path, spawn_projectile, Projectile, ProjectileKind},
+    stats::{DerivedStats, StatModifier, StatModifiers, Stats},
+    targeting::{target_problem, TargetPurpose, Targeting},
+    turn::ACTION_COST,
+    AppState, PlayerClass, SPIKES_DOWN_INDEX, SPIKES_UP_INDEX,
+};
+
+/// Farthest a warrior can charge, in tiles.
+const CHARGE_RANGE: i32 = 5;
+/// Farthest a mage can blink, in tiles.
+const BLINK_RANGE: i32 = 6;
+const FIREBOLT_RANGE: i32 = 6;
+/// Radius of the firebolt's burst around the cell it strikes.
+const FIREBOLT_RADIUS: i32 = 1;
+const AIMED_SHOT_RANGE: i32 = 8;
+/// To-hit bonus of a charge and an aimed shot.
+const CHARGE_ACCURACY: i32 = 2;
+const AIMED_SHOT_ACCURACY: i32 = 5;
+/// Defense and evasion a shield bash knocks off its target, and for how many of its turns.
+const BASH_DAZE: i32 = 2;
+const BASH_DAZE_TURNS: u32 = 3;
+
+/// Hotkeys for the ability bar, in slot order.
+const ABILITY_KEYS: [KeyCode; 4] = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4];
+
+/// Plugin for class abilities: hotkeys, cooldowns and their effects.
+pub struct AbilitiesPlugin;
+
+impl Plugin for AbilitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (
+                    tick_cooldowns,
+                    ability_input.run_if(not(resource_exists::<Targeting>)),
+                )
+                    .chain()
+                    .before(ActionSet),
+                (resolve_abilities, spring_traps).chain().in_set(ActionSet),
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ability {
+    Charge,
+    ShieldBash,
+    Blink,
+    Firebolt,
+    AimedShot,
+    LayTrap,
+}
+
+impl Ability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Ability::Charge => "Charge",
+            Ability::ShieldBash => "Shield bash",
+            Ability::Blink => "Blink",
+            Ability::Firebolt => "Firebolt",
+            Ability::AimedShot => "Aimed shot",
+            Ability::LayTrap => "Lay trap",
+        }
+    }
+
+    /// Range and area radius for abilities that are aimed with the targeting cursor.
+    pub fn targeting(self) -> Option<(i32, i32)> {
+        match self {
+            Ability::Charge => Some((CHARGE_RANGE, 0)),
+            Ability::Blink => Some((BLINK_RANGE, 0)),
+            Ability::Firebolt => Some((FIREBOLT_RANGE, FIREBOLT_RADIUS)),
+            Ability::AimedShot => Some((AIMED_SHOT_RANGE, 0)),
+            Ability::ShieldBash | Ability::LayTrap => None,
+        }
+    }
+
+    /// Turns before the ability can be used again.
+    pub fn cooldown(self) -> u32 {
+        match self {
+            Ability::Charge => 8,
+            Ability::ShieldBash => 5,
+            Ability::Blink => 10,
+            Ability::Firebolt => 3,
+            Ability::AimedShot => 4,
+            Ability::LayTrap => 12,
+        }
+    }
+}
+
+pub struct AbilitySlot {
+    pub ability: Ability,
+    /// Turns left until the ability is ready.
+    pub cooldown: u32,
+}
+
+/// The abilities on an actor's bar, bound to the number keys in order.
+#[derive(Component)]
+pub struct Abilities(pub Vec<AbilitySlot>);
+
+impl Abilities {
+    pub fn for_class(class: PlayerClass) -> Self {
+        let abilities = match class {
+            PlayerClass::Warrior => [Ability::Charge, Ability::ShieldBash],
+            PlayerClass::Mage => [Ability::Blink, Ability::Firebolt],
+            PlayerClass::Ranger => [Ability::AimedShot, Ability::LayTrap],
+        };
+        Abilities(
+            abilities
+                .into_iter()
+                .map(|ability| AbilitySlot { ability, cooldown: 0 })
+                .collect(),
+        )
+    }
+}
+
+/// A hazard that strikes the first monster to step on it.
+#[derive(Component)]
+pub struct Trap {
+    pub attack: AttackProfile,
+}
+
+fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs())
+}
+
+/// Cooldowns count down at the start of each of the owner's turns.
+fn tick_cooldowns(mut query: Query<&mut Abilities, Added<TakingTurn>>) {
+    for mut abilities in &mut query {
+        for slot in &mut abilities.0 {
+            slot.cooldown = slot.cooldown.saturating_sub(1);
+        }
+    }
+}
+
+/// Aimed abilities open the targeting cursor; the rest are used on the spot.
+fn ability_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player_query: Query<(Entity, &Position, &Abilities), PlayersTurn>,
+    mut action_events: EventWriter<ActionEvent>,
+    mut log: ResMut<MessageLog>,
+) {
+    let Ok((player, pos, abilities)) = player_query.get_single() else {
+        return;
+    };
+
+    for (key, slot) in ABILITY_KEYS.iter().zip(&abilities.0) {
+        if !keyboard_input.just_pressed(*key) {
+            continue;
+        }
+        if slot.cooldown > 0 {
+            log.push(format!("{} needs {} more turns.", slot.ability.name(), slot.cooldown));
+            continue;
+        }
+        match slot.ability.targeting() {
+            Some((range, radius)) => commands.insert_resource(Targeting {
+                purpose: TargetPurpose::Ability(slot.ability),
+                cursor: *pos,
+                range,
+                radius,
+            }),
+            None => {
+                action_events.send(ActionEvent {
+                    actor: player,
+                    action: GameAction::UseAbility(slot.ability, None),
+                });
+            }
+        }
+    }
+}
+
+type EnemyData<'a> = (
+    Entity,
+    &'a mut Position,
+    &'a DerivedStats,
+    Option<&'a Resistances>,
+    &'a mut Energy,
+    &'a mut StatModifiers,
+);
+
+type UserData<'a> = (&'a mut Position, &'a DerivedStats, &'a mut Abilities, &'a mut Energy, Has<Player>);
+
+#[allow(clippy::too_many_arguments)]
+fn resolve_abilities(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    combat: Combat,
+    map: Res<DungeonMap>,
+    mut action_events: EventReader<ActionEvent>,
+    mut user_query: Query<UserData, Without<Enemy>>,
+    mut enemy_query: Query<EnemyData, With<Enemy>>,
+    blocker_query: Query<&Position, (With<BlocksSight>, Without<Energy>)>,
+    mut result_events: EventWriter<CombatResult>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+
+    for event in action_events.read() {
+        let GameAction::UseAbility(ability, target) = event.action else {
+            continue;
+        };
+        let Ok((mut pos, DerivedStats(stats), mut abilities, mut energy, is_player)) =
+            user_query.get_mut(event.actor)
+        else {
+            continue;
+        };
+        let Some(slot) = abilities.0.iter_mut().find(|slot| slot.ability == ability) else {
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        };
+        if slot.cooldown > 0 {
+            log.push(format!("{} needs {} more turns.", ability.name(), slot.cooldown));
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        }
+
+        // Abilities only reach monsters the user can actually see, and only the aimed-at one if there is one
+        let origin = *pos;
+        let mut visible: Vec<(Entity, Position)> = enemy_query
+            .iter()
+            .filter(|(_, enemy_pos, ..)| map.is_visible(**enemy_pos) && target.is_none_or(|t| t == **enemy_pos))
+            .map(|(entity, enemy_pos, ..)| (entity, *enemy_pos))
+            .collect();
+        visible.sort_by_key(|(_, enemy_pos)| distance(origin, *enemy_pos));
+        let occupied = |p: Position| enemy_query.iter().any(|(_, enemy_pos, ..)| *enemy_pos == p);
+        let nearest_within = |range: i32| {
+            visible
+                .iter()
+                .copied()
+                .find(|(_, p)| distance(origin, *p) <= range && has_line_of_sight(&map, &blockers, origin, *p))
+        };
+        let user = event.actor;
+
+        let succeeded = match ability {
+            Ability::Charge => {
+                // Rush along a clear straight line and strike the first monster on it
+                let target = visible.iter().copied().find(|(_, target_pos)| {
+                    let (dx, dy) = (target_pos.x - origin.x, target_pos.y - origin.y);
+                    let dist = distance(origin, *target_pos);
+                    let straight = dx == 0 || dy == 0 || dx.abs() == dy.abs();
+                    let step = IVec2::new(dx.signum(), dy.signum());
+                    straight
+                        && (2..=CHARGE_RANGE).contains(&dist)
+                        && (1..dist).all(|i| {
+                            let p = origin.offset(step * i);
+                            map.is_walkable(p) && !occupied(p)
+                        })
+                });
+                match target {
+                    Some((target, target_pos)) => {
+                        let step = IVec2::new((target_pos.x - origin.x).signum(), (target_pos.y - origin.y).signum());
+                        *pos = target_pos.offset(-step);
+                        log.push("You charge!");
+                        let charging = Stats {
+                            accuracy: stats.accuracy + CHARGE_ACCURACY,
+                            ..*stats
+                        };
+                        strike(
+                            (&mut rng, combat.rules(), &mut result_events),
+                            (user, &charging),
+                            target,
+                            &enemy_query,
+                            AttackProfile {
+                                damage: Dice::new(1, 8, 0),
+                                damage_type: DamageType::Blunt,
+                            },
+                        );
+                        true
+                    }
+                    None => {
+                        log.push("Nothing to charge at.");
+                        false
+                    }
+                }
+            }
+            Ability::ShieldBash => match nearest_within(1) {
+                Some((target, target_pos)) => {
+                    let outcome = strike(
+                        (&mut rng, combat.rules(), &mut result_events),
+                        (user, stats),
+                        target,
+                        &enemy_query,
+                        AttackProfile {
+                            damage: Dice::new(1, 6, 0),
+                            damage_type: DamageType::Blunt,
+                        },
+                    );
+                    // A landed bash knocks the monster back, staggers it and leaves its guard down
+                    let pushed = target_pos.offset(IVec2::new(target_pos.x - origin.x, target_pos.y - origin.y));
+                    let room_behind = map.is_walkable(pushed) && !occupied(pushed);
+                    if matches!(outcome, Some(HitOutcome::Hit | HitOutcome::Critical))
+                        && let Ok((_, mut enemy_pos, _, _, mut enemy_energy, mut modifiers)) = enemy_query.get_mut(target)
+                    {
+                        if room_behind {
+                            *enemy_pos = pushed;
+                        }
+                        enemy_energy.0 -= ACTION_COST;
+                        modifiers.0.push(StatModifier {
+                            bonus: Stats {
+                                defense: -BASH_DAZE,
+                                evasion: -BASH_DAZE,
+                                ..default()
+                            },
+                            turns: Some(BASH_DAZE_TURNS),
+                        });
+                    }
+                    true
+                }
+                None => {
+                    log.push("Nothing to bash.");
+                    false
+                }
+            },
+            Ability::Blink => {
+                // Land where aimed, or else on the seen tile farthest from any visible monster
+                let can_land = |p: &Position| {
+                    map.is_visible(*p)
+                        && map.is_walkable(*p)
+                        && !occupied(*p)
+                        && distance(origin, *p) <= BLINK_RANGE
+                };
+                let landing = match target {
+                    Some(target) => Some(target).filter(can_land),
+                    None => map
+                        .positions()
+                        .filter(|p| can_land(p) && distance(origin, *p) >= 3)
+                        .max_by_key(|p| visible.iter().map(|(_, e)| distance(*p, *e)).min().unwrap_or(0)),
+                };
+                match landing {
+                    Some(landing) => {
+                        *pos = landing;
+                        log.push("You blink away.");
+                        true
+                    }
+                    None => {
+                        log.push("There is nowhere to blink to.");
+                        false
+                    }
+                }
+            }
+            Ability::Firebolt => {
+                // The bolt bursts on the aimed cell, or on the nearest monster, scorching everything around it
+                let impact = match target {
+                    Some(target) => {
+                        Some(target).filter(|t| target_problem((&map, &blockers), origin, *t, FIREBOLT_RANGE).is_none())
+                    }
+                    None => nearest_within(FIREBOLT_RANGE).map(|(_, p)| p),
+                };
+                match impact {
+                    Some(impact) => {
+                        let casting = Stats {
+                            attack: stats.magic,
+                            ..*stats
+                        };
+                        spawn_projectile(
+                            &mut commands,
+                            &asset_server,
+                            &mut texture_atlas_layouts,
+                            ProjectileKind::Bolt(DamageType::Fire),
+                            origin,
+                            Projectile::new(
+                                (user, is_player),
+                                casting,
+                                AttackProfile {
+                                    damage: Dice::new(2, 6, 0),
+                                    damage_type: DamageType::Fire,
+                                },
+                                flight_path(origin, impact, FIREBOLT_RANGE, true),
+                                FIREBOLT_RADIUS,
+                            ),
+                        );
+                        true
+                    }
+                    None => {
+                        log.push("No target in range.");
+                        false
+                    }
+                }
+            }
+            Ability::AimedShot => match nearest_within(AIMED_SHOT_RANGE) {
+                Some((_, target_pos)) => {
+                    let aiming = Stats {
+                        accuracy: stats.accuracy + AIMED_SHOT_ACCURACY,
+                        ..*stats
+                    };
+                    spawn_projectile(
+                        &mut commands,
+                        &asset_server,
+                        &mut texture_atlas_layouts,
+                        ProjectileKind::Arrow,
+                        origin,
+                        Projectile::new(
+                            (user, is_player),
+                            aiming,
+                            AttackProfile {
+                                damage: Dice::new(1, 8, 0),
+                                damage_type: DamageType::Pierce,
+                            },
+                            flight_path(origin, target_pos, AIMED_SHOT_RANGE, false),
+                            0,
+                        ),
+                    );
+                    true
+                }
+                None => {
+                    log.push("No target in range.");
+                    false
+                }
+            },
+            Ability::LayTrap => {
+                spawn_trap(&mut commands, &asset_server, &mut texture_atlas_layouts, *pos);
+                log.push("You set a spike trap.");
+                true
+            }
+        };
+
+        if succeeded {
+            slot.cooldown = ability.cooldown();
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
+
+/// Rolls an ability's attack against a monster and reports how it went.
+fn strike(
+    (rng, rules, result_events): (&mut impl Rng, &CombatRules, &mut EventWriter<CombatResult>),
+    attacker: (Entity, &Stats),
+    target: Entity,
+    enemy_query: &Query<EnemyData, With<Enemy>>,
+    weapon: AttackProfile,
+) -> Option<HitOutcome> {
+    let (_, _, DerivedStats(target_stats), resistances, ..) = enemy_query.get(target).ok()?;
+    let result = roll_attack(rng, rules, attacker, (target, target_stats, resistances), weapon, false);
+    let outcome = result.outcome;
+    result_events.send(result);
+    Some(outcome)
+}
+
+fn spawn_trap(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    pos: Position,
+) {
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 17, 26, None, None);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..default()
+            },
+            texture: asset_server.load("tiles.png"),
+            transform: Transform::from_translation(pos.to_world(RenderLayer::Decal.z())),
+            ..default()
+        },
+        TextureAtlas {
+            layout: texture_atlas_layouts.add(layout),
+            index: SPIKES_DOWN_INDEX,
+        },
+        pos,
+        RenderLayer::Decal,
+        Name::new("the spike trap"),
+        Trap {
+            attack: AttackProfile {
+                damage: Dice::new(2, 4, 2),
+                damage_type: DamageType::Pierce,
+            },
+        },
+        LevelEntity,
+    ));
+}
+
+type VictimData<'a> = (Entity, &'a Position, &'a DerivedStats, Option<&'a Resistances>, &'a mut Energy);
+
+/// Monsters stepping onto an armed trap are struck and held in place for a turn.
+fn spring_traps(
+    mut commands: Commands,
+    combat: Combat,
+    mut trap_query: Query<(Entity, &Position, &Trap, &mut TextureAtlas)>,
+    mut enemy_query: Query<VictimData, (With<Enemy>, Changed<Position>)>,
+    mut result_events: EventWriter<CombatResult>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (enemy, enemy_pos, DerivedStats(stats), resistances, mut energy) in &mut enemy_query {
+        let Some((trap, _, Trap { attack }, mut atlas)) =
+            trap_query.iter_mut().find(|(_, trap_pos, ..)| *trap_pos == enemy_pos)
+        else {
+            continue;
+        };
+
+        result_events.send(roll_attack(
+            &mut rng,
+            combat.rules(),
+            (trap, &Stats::default()),
+            (enemy, stats, resistances),
+            *attack,
+            false,
+        ));
+        energy.0 -= ACTION_COST;
+        atlas.index = SPIKES_UP_INDEX;
+        commands.entity(trap).remove::<Trap>();
+    }
+}
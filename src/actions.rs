@@ -0,0 +1,258 @@
+use bevy::prelude::*;
+
+use crate::{
+    abilities::Ability,
+    components::{Digger, Enemy, Energy, Health, Player, PlayersTurn, Position},
+    dig::{BlastEvent, DigEvent, BLAST_POWER, BLAST_RADIUS},
+    map::{DungeonMap, Tile},
+    spells::SpellId,
+    targeting::Targeting,
+    turn::{end_turn, TurnSet},
+    AppState, PlayerClass,
+};
+
+/// Plugin that turns player input into `GameAction`s and resolves every actor's actions by the same rules.
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ActionEvent>()
+            .configure_sets(Update, ActionSet.after(TurnSet))
+            .add_systems(
+                Update,
+                (
+                    player_input
+                        .run_if(not(resource_exists::<Targeting>))
+                        .before(ActionSet),
+                    (
+                        resolve_wait,
+                        resolve_move,
+                        resolve_dig,
+                        resolve_blast,
+                    )
+                        .chain()
+                        .in_set(ActionSet),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Resolves queued actions; anything producing `ActionEvent`s should run before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionSet;
+
+/// Something an actor intends to do with its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameAction {
+    Move(IVec2),
+    Attack(Entity),
+    Wait,
+    PickUp,
+    Open(Position),
+    Dig(Position),
+    Blast,
+    /// Drink or read the potion or scroll in this slot of the actor's inventory.
+    UseItem(usize),
+    Descend,
+    /// Use an ability, aimed at a cell if it needs a target.
+    UseAbility(Ability, Option<Position>),
+    /// Fire the actor's ranged weapon at a cell.
+    Shoot(Position),
+    /// Cast a known spell, aimed at a cell if it needs a target.
+    Cast(SpellId, Option<Position>),
+}
+
+#[derive(Event, Debug)]
+pub struct ActionEvent {
+    pub actor: Entity,
+    pub action: GameAction,
+}
+
+/// What stepping in `dir` means from `from`: attacking the `hostile` standing there, opening a shut door, or just walking.
+pub fn bump_action(map: &DungeonMap, from: Position, dir: IVec2, hostile: Option<Entity>) -> GameAction {
+    let target = from.offset(dir);
+    if let Some(hostile) = hostile {
+        return GameAction::Attack(hostile);
+    }
+    match map.tile(target) {
+        Tile::Door { open: false } => GameAction::Open(target),
+        _ => GameAction::Move(dir),
+    }
+}
+
+/// A successful action ends the turn; a failed one costs monsters their turn but lets the player choose again.
+pub fn settle(commands: &mut Commands, actor: Entity, energy: &mut Energy, is_player: bool, succeeded: bool) {
+    if succeeded || !is_player {
+        end_turn(commands, actor, energy);
+    }
+}
+
+fn player_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    map: Res<DungeonMap>,
+    player_query: Query<(Entity, &Position), PlayersTurn>,
+    enemy_query: Query<(Entity, &Position), With<Enemy>>,
+    mut action_events: EventWriter<ActionEvent>,
+) {
+    let Ok((player, pos)) = player_query.get_single() else {
+        return;
+    };
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    let mut dir = IVec2::ZERO;
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        dir.y += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        dir.y -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        dir.x -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        dir.x += 1;
+    }
+
+    let action = if dir != IVec2::ZERO {
+        // Shift + direction digs into the adjacent wall instead of moving
+        if shift {
+            GameAction::Dig(pos.offset(dir))
+        } else {
+            let target = pos.offset(dir);
+            let hostile = enemy_query
+                .iter()
+                .find(|(_, enemy_pos)| **enemy_pos == target)
+                .map(|(enemy, _)| enemy);
+            bump_action(&map, *pos, dir, hostile)
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Period) && shift {
+        GameAction::Descend
+    } else if keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::Period]) {
+        GameAction::Wait
+    } else if keyboard_input.just_pressed(KeyCode::KeyB) {
+        GameAction::Blast
+    } else if keyboard_input.just_pressed(KeyCode::KeyG) {
+        GameAction::PickUp
+    } else {
+        return;
+    };
+
+    action_events.send(ActionEvent {
+        actor: player,
+        action,
+    });
+}
+
+fn resolve_wait(
+    mut commands: Commands,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<&mut Energy>,
+) {
+    for event in action_events.read() {
+        if event.action != GameAction::Wait {
+            continue;
+        }
+        if let Ok(mut energy) = actor_query.get_mut(event.actor) {
+            end_turn(&mut commands, event.actor, &mut energy);
+        }
+    }
+}
+
+type MoverData<'a> = (Entity, &'a mut Position, &'a mut Energy, Has<Player>, Has<Enemy>);
+
+/// Creatures never share a cell: monsters trade places with an ally in their way, anyone else is blocked.
+fn resolve_move(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<MoverData, With<Health>>,
+) {
+    for event in action_events.read() {
+        let GameAction::Move(dir) = event.action else {
+            continue;
+        };
+        let Ok((_, pos, _, _, is_enemy)) = actor_query.get(event.actor) else {
+            continue;
+        };
+
+        let target = pos.offset(dir);
+        let occupant = actor_query
+            .iter()
+            .find(|(other, other_pos, ..)| *other != event.actor && **other_pos == target)
+            .map(|(other, _, _, _, other_is_enemy)| (other, other_is_enemy));
+        let succeeded = dir.x.abs() <= 1
+            && dir.y.abs() <= 1
+            && map.is_walkable(target)
+            && occupant.is_none_or(|(_, other_is_enemy)| is_enemy && other_is_enemy);
+
+        match occupant {
+            Some((other, _)) if succeeded => {
+                if let Ok([(_, mut pos, ..), (_, mut other_pos, ..)]) = actor_query.get_many_mut([event.actor, other]) {
+                    std::mem::swap(&mut *pos, &mut *other_pos);
+                }
+            }
+            None if succeeded => {
+                if let Ok((_, mut pos, ..)) = actor_query.get_mut(event.actor) {
+                    *pos = target;
+                }
+            }
+            _ => {}
+        }
+        if let Ok((_, _, mut energy, is_player, _)) = actor_query.get_mut(event.actor) {
+            settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+        }
+    }
+}
+
+fn resolve_dig(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<(&Position, &Digger, &mut Energy, Has<Player>)>,
+    mut dig_events: EventWriter<DigEvent>,
+) {
+    for event in action_events.read() {
+        let GameAction::Dig(target) = event.action else {
+            continue;
+        };
+        let Ok((pos, digger, mut energy, is_player)) = actor_query.get_mut(event.actor) else {
+            continue;
+        };
+
+        let succeeded = pos.is_adjacent(target) && map.is_diggable(target);
+        if succeeded {
+            dig_events.send(DigEvent {
+                target,
+                power: digger.power,
+            });
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
+
+fn resolve_blast(
+    mut commands: Commands,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<(&Position, Option<&PlayerClass>, &mut Energy, Has<Player>)>,
+    mut blast_events: EventWriter<BlastEvent>,
+) {
+    for event in action_events.read() {
+        if event.action != GameAction::Blast {
+            continue;
+        }
+        let Ok((pos, class, mut energy, is_player)) = actor_query.get_mut(event.actor) else {
+            continue;
+        };
+
+        let succeeded = matches!(class, Some(PlayerClass::Mage));
+        if succeeded {
+            blast_events.send(BlastEvent {
+                center: *pos,
+                radius: BLAST_RADIUS,
+                power: BLAST_POWER,
+            });
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::{seq::IteratorRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    actions::{bump_action, ActionEvent, ActionSet, GameAction},
+    combat::{capitalize, CombatResult, RangedAttack},
+    components::{AwareOfPlayer, BlocksSight, Enemy, Health, Mana, Player, Position, TakingTurn},
+    dig::{BlastEvent, DigEvent},
+    fov::has_line_of_sight,
+    hud::MessageLog,
+    map::DungeonMap,
+    pathfinding::{astar, ItemMap, MovementType, PathCosts, PlayerMaps, STEPS},
+    spells::{SpellEffect, SpellShape, Spellbook, Spells},
+    stats::DerivedStats,
+    targeting::target_problem,
+    turn::TurnSet,
+    AppState,
+};
+
+/// Turns a hunter keeps after its quarry once it has lost sight of it.
+const LOSE_TRACK_TURNS: u32 = 10;
+/// Turns spent poking around a noise or a last sighting.
+const INVESTIGATE_TURNS: u32 = 8;
+/// Turns a fleeing monster keeps running after it last saw the player.
+const FLEE_TURNS: u32 = 10;
+/// Monsters that flee at all do so at or below this share of their health.
+const FLEE_HEALTH_PERCENT: i32 = 25;
+/// Extra cost of pathing through a cell another monster stands in, so they flow around each other.
+const CROWD_COST: u32 = 5;
+/// Archers and casters back away from anyone closer than this.
+const STANDOFF_DISTANCE: i32 = 3;
+/// Monsters that can heal themselves do so at or below this share of their health.
+const HEAL_HEALTH_PERCENT: i32 = 50;
+/// Followers at ease stay within this many steps of their leader.
+const FOLLOW_DISTANCE: i32 = 2;
+/// Swarms at ease keep within this many steps of their nest.
+const NEST_RADIUS: i32 = 3;
+/// Chance that a noise in earshot wakes a sleeping monster.
+const WAKE_CHANCE: f64 = 0.5;
+
+/// How far each kind of noise carries, in tiles.
+const FOOTSTEP_NOISE: i32 = 2;
+const COMBAT_NOISE: i32 = 6;
+const DIG_NOISE: i32 = 5;
+const BLAST_NOISE: i32 = 12;
+
+/// Plugin for monster behaviour: what they are up to, what they notice and where they go.
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (rally_packs, enemy_ai).chain().after(TurnSet).before(ActionSet),
+                hear_noises.after(ActionSet),
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// How a kind of monster fights, set per monster in `monsters.ron`.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum AiProfile {
+    /// Closes in to fight hand to hand, and runs when badly hurt.
+    #[default]
+    Melee,
+    /// Closes in and fights to the end.
+    Mindless,
+    /// Keeps its distance and shoots whenever it has a clear line.
+    Archer,
+    /// Keeps its distance and casts whatever spell will do it the most good.
+    Caster,
+}
+
+impl AiProfile {
+    fn keeps_distance(self) -> bool {
+        matches!(self, AiProfile::Archer | AiProfile::Caster)
+    }
+}
+
+/// How a group of monsters works together, set per group in `encounters.ron`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Tactic {
+    /// Each goes its own way.
+    #[default]
+    Loose,
+    /// Sticks close to its leader and rallies to it, and scatters once it falls.
+    Follow,
+    /// Spreads out to come at the player from every side at once.
+    Flank,
+    /// Keeps near its nest and boils out all together.
+    Swarm,
+}
+
+/// The shared state of a group that spawned together, on an entity of its own.
+#[derive(Component, Debug)]
+pub struct Pack {
+    pub tactic: Tactic,
+    pub leader: Option<Entity>,
+    /// Where the group first gathered.
+    pub home: Position,
+}
+
+/// Belongs to a pack; `slot` tells its members apart, e.g. for the side each flanks from.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PackMember {
+    pub pack: Entity,
+    pub slot: usize,
+}
+
+/// What a monster is currently doing.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiState {
+    /// Does nothing until a noise or a blow wakes it.
+    Sleeping,
+    /// Roams between random spots on the level.
+    Wandering { goal: Option<Position> },
+    /// Heads for something it heard and looks around there.
+    Investigating { spot: Position, turns_left: u32 },
+    /// Chases the player to where it last saw them.
+    Hunting { last_seen: Position, turns_unseen: u32 },
+    /// Runs from where it last saw the player.
+    Fleeing { from: Position, turns_left: u32 },
+}
+
+fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs())
+}
+
+/// Monsters in earshot of a noise wake up or come to look; anything the player strikes turns on them.
+#[allow(clippy::too_many_arguments)]
+fn hear_noises(
+    mut action_events: EventReader<ActionEvent>,
+    mut result_events: EventReader<CombatResult>,
+    mut dig_events: EventReader<DigEvent>,
+    mut blast_events: EventReader<BlastEvent>,
+    map: Res<DungeonMap>,
+    player_query: Query<(Entity, &Position), With<Player>>,
+    mut monster_query: Query<(Entity, &Position, &mut AiState, Option<&Name>)>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+    let player = player_query.get_single().ok();
+
+    let mut noises: Vec<(Position, i32)> = Vec::new();
+    for event in action_events.read() {
+        if let (GameAction::Move(_), Some((player, pos))) = (event.action, player)
+            && event.actor == player
+        {
+            noises.push((*pos, FOOTSTEP_NOISE));
+        }
+    }
+    for result in result_events.read() {
+        if let Ok((_, pos, ..)) = monster_query.get(result.target) {
+            noises.push((*pos, COMBAT_NOISE));
+        }
+        if let Some((player, player_pos)) = player
+            && result.attacker == player
+            && let Ok((_, _, mut state, _)) = monster_query.get_mut(result.target)
+        {
+            *state = AiState::Hunting {
+                last_seen: *player_pos,
+                turns_unseen: 0,
+            };
+        }
+    }
+    noises.extend(dig_events.read().map(|event| (event.target, DIG_NOISE)));
+    noises.extend(blast_events.read().map(|event| (event.center, BLAST_NOISE)));
+
+    for (spot, loudness) in noises {
+        for (_, pos, mut state, name) in &mut monster_query {
+            if distance(*pos, spot) > loudness {
+                continue;
+            }
+            match *state {
+                AiState::Sleeping if rng.gen_bool(WAKE_CHANCE) => {
+                    if map.is_visible(*pos) {
+                        let name = name.map_or("Something", |name| name.as_str());
+                        log.push(format!("{} wakes up.", capitalize(name)));
+                    }
+                }
+                AiState::Wandering { .. } | AiState::Investigating { .. } => {}
+                _ => continue,
+            }
+            *state = AiState::Investigating {
+                spot,
+                turns_left: INVESTIGATE_TURNS,
+            };
+        }
+    }
+}
+
+type MemberData<'a> = (&'a PackMember, &'a Position, &'a mut AiState, Option<&'a AiProfile>, Option<&'a Name>);
+
+/// Packs act as one: a member sighting the player rouses the rest, and followers whose
+/// leader has fallen lose heart and run.
+fn rally_packs(
+    mut pack_query: Query<(Entity, &mut Pack)>,
+    mut member_query: Query<MemberData>,
+    health_query: Query<&Health>,
+    map: Res<DungeonMap>,
+    mut log: ResMut<MessageLog>,
+) {
+    for (pack_entity, mut pack) in &mut pack_query {
+        if let Some(leader) = pack.leader
+            && !health_query.get(leader).is_ok_and(|health| health.0 > 0)
+        {
+            pack.leader = None;
+            pack.tactic = Tactic::Loose;
+            let mut seen_running = false;
+            for (member, pos, mut state, profile, _) in &mut member_query {
+                if member.pack != pack_entity || profile == Some(&AiProfile::Mindless) || *state == AiState::Sleeping {
+                    continue;
+                }
+                let from = match *state {
+                    AiState::Hunting { last_seen, .. } => last_seen,
+                    _ => *pos,
+                };
+                *state = AiState::Fleeing {
+                    from,
+                    turns_left: FLEE_TURNS,
+                };
+                seen_running |= map.is_visible(*pos);
+            }
+            if seen_running {
+                log.push("With their leader fallen, the rest break and run!".to_string());
+            }
+            continue;
+        }
+        if pack.tactic == Tactic::Loose {
+            continue;
+        }
+
+        let sighting = member_query.iter().find_map(|(member, _, state, ..)| match *state {
+            AiState::Hunting {
+                last_seen,
+                turns_unseen: 0,
+            } if member.pack == pack_entity => Some(last_seen),
+            _ => None,
+        });
+        let Some(last_seen) = sighting else {
+            continue;
+        };
+        let mut roused = false;
+        for (member, _, mut state, ..) in &mut member_query {
+            if member.pack == pack_entity
+                && matches!(
+                    *state,
+                    AiState::Sleeping | AiState::Wandering { .. } | AiState::Investigating { .. }
+                )
+            {
+                *state = AiState::Hunting {
+                    last_seen,
+                    turns_unseen: 0,
+                };
+                roused = true;
+            }
+        }
+        if roused
+            && let Some(leader) = pack.leader
+            && let Ok((_, pos, _, _, name)) = member_query.get(leader)
+            && map.is_visible(*pos)
+        {
+            let name = capitalize(name.map_or("something", |name| name.as_str()));
+            log.push(format!("{name} rallies its followers!"));
+        }
+    }
+}
+
+type MonsterData<'a> = (
+    Entity,
+    &'a Position,
+    &'a mut AiState,
+    &'a Health,
+    &'a DerivedStats,
+    Option<&'a AiProfile>,
+    Option<&'a Name>,
+    Option<&'a MovementType>,
+    Has<AwareOfPlayer>,
+    Kit<'a>,
+    Option<&'a PackMember>,
+);
+
+/// What a monster has to fight with at range.
+type Kit<'a> = (Option<&'a RangedAttack>, Option<&'a Mana>, Option<&'a Spellbook>);
+
+/// How far off a monster can hurt anyone, with its ranged weapon or its spells.
+fn reach((ranged, _, spellbook): Kit, spells: &Spells) -> i32 {
+    let spell_reach = spellbook
+        .into_iter()
+        .flat_map(|book| &book.0)
+        .filter_map(|&id| spells.get(id))
+        .filter(|spell| spell.damage.is_some())
+        .map(|spell| if spell.shape.is_aimed() { spell.range } else { spell.shape.radius() });
+    spell_reach.chain(ranged.map(|ranged| ranged.range)).max().unwrap_or(0)
+}
+
+/// The spell or shot a monster would loose at `target` right now, if it has one that will land.
+/// Up close it only blinks away, heals or casts around itself; otherwise it heals when wounded
+/// and picks the dearest spell that hits, falling back on its bow.
+fn ranged_action(
+    (map, blockers): (&DungeonMap, &HashSet<Position>),
+    (pos, target): (Position, Position),
+    wounded: bool,
+    (ranged, mana, spellbook): Kit,
+    spells: &Spells,
+    occupied: &[Position],
+) -> Option<GameAction> {
+    let adjacent = distance(pos, target) <= 1;
+    let escape = |range: i32| {
+        map.positions()
+            .filter(|p| map.is_walkable(*p) && !occupied.contains(p) && distance(*p, target) > 1)
+            .filter(|p| target_problem((map, blockers), pos, *p, range).is_none())
+            .max_by_key(|p| distance(*p, target))
+    };
+
+    let cast = spellbook
+        .into_iter()
+        .flat_map(|book| &book.0)
+        .filter_map(|&id| spells.get(id).map(|spell| (id, spell)))
+        .filter(|(_, spell)| mana.is_some_and(|mana| mana.current >= spell.cost))
+        .filter_map(|(id, spell)| {
+            if spell.effects.contains(&SpellEffect::Teleport) {
+                if !adjacent {
+                    return None;
+                }
+                return Some((2, spell.cost, GameAction::Cast(id, Some(escape(spell.range)?))));
+            }
+            let aimed = spell.shape.is_aimed();
+            let lands = match spell.shape {
+                SpellShape::Burst { radius } => distance(pos, target) <= radius,
+                _ => aimed && target_problem((map, blockers), pos, target, spell.range).is_none(),
+            };
+            let heals = spell.effects.iter().any(|effect| matches!(effect, SpellEffect::Heal(_)));
+            let priority = if wounded && heals && (lands || !aimed) {
+                1
+            } else if spell.damage.is_some() && lands && !(adjacent && aimed) {
+                0
+            } else {
+                return None;
+            };
+            Some((priority, spell.cost, GameAction::Cast(id, aimed.then_some(target))))
+        })
+        .max_by_key(|(priority, cost, _)| (*priority, *cost))
+        .map(|(.., action)| action);
+
+    cast.or_else(|| {
+        let ranged = ranged?;
+        let clear = !adjacent && distance(pos, target) <= ranged.range && has_line_of_sight(map, blockers, pos, target);
+        clear.then_some(GameAction::Shoot(target))
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn enemy_ai(
+    mut enemy_query: Query<MonsterData, (With<Enemy>, With<TakingTurn>)>,
+    crowd_query: Query<&Position, With<Enemy>>,
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    player_query: Query<(Entity, &Position), With<Player>>,
+    map: Res<DungeonMap>,
+    (player_maps, item_map): (Option<Res<PlayerMaps>>, Option<Res<ItemMap>>),
+    pack_query: Query<&Pack>,
+    spells: Spells,
+    mut action_events: EventWriter<ActionEvent>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+    let player = player_query.get_single().ok();
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+
+    for (entity, pos, mut state, health, DerivedStats(stats), profile, name, movement, aware, kit, member) in
+        &mut enemy_query
+    {
+        let seen = player.filter(|_| aware).map(|(_, player_pos)| *player_pos);
+        let fearless = profile == Some(&AiProfile::Mindless);
+        let hurt = !fearless && health.0 * 100 <= stats.max_hp * FLEE_HEALTH_PERCENT;
+        let name = capitalize(name.map_or("something", |name| name.as_str()));
+
+        let next = match (*state, seen) {
+            (AiState::Sleeping, _) => AiState::Sleeping,
+            (AiState::Fleeing { .. }, Some(player_pos)) => AiState::Fleeing {
+                from: player_pos,
+                turns_left: FLEE_TURNS,
+            },
+            (AiState::Fleeing { turns_left: 0, .. }, None) => AiState::Wandering { goal: None },
+            (AiState::Fleeing { from, turns_left }, None) => AiState::Fleeing {
+                from,
+                turns_left: turns_left - 1,
+            },
+            (AiState::Hunting { last_seen: from, .. }, _) | (_, Some(from)) if hurt => AiState::Fleeing {
+                from: seen.unwrap_or(from),
+                turns_left: FLEE_TURNS,
+            },
+            (_, Some(player_pos)) => AiState::Hunting {
+                last_seen: player_pos,
+                turns_unseen: 0,
+            },
+            (AiState::Hunting { turns_unseen, .. }, None) if turns_unseen >= LOSE_TRACK_TURNS => {
+                AiState::Wandering { goal: None }
+            }
+            // Reached the last sighting without finding anyone: search the area
+            (AiState::Hunting { last_seen, .. }, None) if last_seen == *pos => AiState::Investigating {
+                spot: last_seen,
+                turns_left: INVESTIGATE_TURNS,
+            },
+            (AiState::Hunting { last_seen, turns_unseen }, None) => AiState::Hunting {
+                last_seen,
+                turns_unseen: turns_unseen + 1,
+            },
+            (AiState::Investigating { turns_left: 0, .. }, None) => AiState::Wandering { goal: None },
+            (AiState::Investigating { spot, turns_left }, None) => AiState::Investigating {
+                spot,
+                turns_left: turns_left - 1,
+            },
+            (AiState::Wandering { goal }, None) => AiState::Wandering {
+                goal: goal.filter(|goal| goal != pos),
+            },
+        };
+        if map.is_visible(*pos) {
+            match (*state, next) {
+                (AiState::Hunting { .. }, AiState::Hunting { .. }) | (AiState::Fleeing { .. }, AiState::Fleeing { .. }) => {}
+                (_, AiState::Hunting { .. }) => log.push(format!("{name} notices you!")),
+                (_, AiState::Fleeing { .. }) => log.push(format!("{name} turns to flee!")),
+                _ => {}
+            }
+        }
+        *state = next;
+
+        let movement = movement.copied().unwrap_or_default();
+        let terrain = PathCosts::new(&map, movement);
+        let crowd: Vec<_> = crowd_query
+            .iter()
+            .filter(|p| *p != pos)
+            .map(|p| (*p, terrain.cost(*p).map(|cost| cost + CROWD_COST)))
+            .collect();
+        let costs = PathCosts::new(&map, movement).with_overrides(crowd);
+        let step_toward = |goal: Position| {
+            astar(*pos, goal, |p| costs.cost(p))
+                .and_then(|path| path.first().copied())
+                .map(|next| IVec2::new(next.x - pos.x, next.y - pos.y))
+        };
+        // Walkers share the flow fields around the player instead of searching on their own
+        let shared = player_maps.as_ref().filter(|_| movement == MovementType::Walk);
+        let lure = item_map
+            .as_ref()
+            .filter(|_| movement == MovementType::Walk)
+            .and_then(|items| items.toward.direction(*pos));
+        let step_away = |from: Position| match shared {
+            Some(maps) => maps.away.direction(*pos),
+            None => STEPS
+                .into_iter()
+                .filter(|step| costs.cost(pos.offset(*step)).is_some())
+                .max_by_key(|step| distance(pos.offset(*step), from))
+                .filter(|step| distance(pos.offset(*step), from) > distance(*pos, from)),
+        };
+
+        // Archers and casters fight at a distance while they can see their quarry
+        let reach = reach(kit, &spells);
+        let keeps_distance = profile.is_some_and(|profile| profile.keeps_distance()) && reach > 0;
+        let standoff = match (*state, seen) {
+            (AiState::Hunting { .. }, Some(target)) if keeps_distance => Some(target),
+            _ => None,
+        };
+
+        let pack = member.and_then(|member| Some((member, pack_query.get(member.pack).ok()?)));
+        // Pack hunters each make for their own side of the quarry
+        let flank = match (pack, seen) {
+            (Some((member, Pack { tactic: Tactic::Flank, .. })), Some(target)) if !pos.is_adjacent(target) => {
+                let side = target.offset(STEPS[member.slot % STEPS.len()]);
+                map.is_walkable(side).then_some(side)
+            }
+            _ => None,
+        };
+        let (leader, nest) = match pack {
+            Some((_, Pack { tactic: Tactic::Follow, leader: Some(leader), .. })) if *leader != entity => {
+                (crowd_query.get(*leader).ok(), None)
+            }
+            Some((_, Pack { tactic: Tactic::Swarm, home, .. })) => (None, Some(*home)),
+            _ => (None, None),
+        };
+
+        let dir = match &mut *state {
+            AiState::Sleeping => None,
+            AiState::Wandering { goal } => match leader {
+                // Followers at ease trail their leader rather than roaming
+                Some(leader_pos) => {
+                    *goal = None;
+                    (distance(*pos, *leader_pos) > FOLLOW_DISTANCE)
+                        .then(|| step_toward(*leader_pos))
+                        .flatten()
+                }
+                // Loose walkers with nowhere to be drift toward whatever lies about on the floor
+                None if goal.is_none() && nest.is_none() && lure.is_some() => lure,
+                None => {
+                    let destination = *goal.get_or_insert_with(|| {
+                        map.positions()
+                            .filter(|p| map.is_walkable(*p))
+                            .filter(|p| nest.is_none_or(|nest| distance(*p, nest) <= NEST_RADIUS))
+                            .choose(&mut rng)
+                            .unwrap_or(*pos)
+                    });
+                    let dir = step_toward(destination);
+                    if dir.is_none() {
+                        *goal = None;
+                    }
+                    dir
+                }
+            },
+            // Once there, look around at random
+            AiState::Investigating { spot, .. } if *spot == *pos => Some(STEPS[rng.gen_range(0..STEPS.len())]),
+            AiState::Investigating { spot, .. } => step_toward(*spot),
+            AiState::Hunting { last_seen, .. } => match (standoff, flank, shared) {
+                (Some(target), ..) if distance(*pos, target) < STANDOFF_DISTANCE => step_away(target),
+                // In reach but out of arrows or mana for now: hold the line and wait
+                (Some(target), ..) if distance(*pos, target) <= reach && has_line_of_sight(&map, &blockers, *pos, target) => None,
+                (_, Some(side), _) => step_toward(side),
+                (.., Some(maps)) if seen == Some(*last_seen) => maps.toward.direction(*pos),
+                _ => step_toward(*last_seen),
+            },
+            AiState::Fleeing { from, .. } => step_away(*from),
+        };
+
+        let wounded = health.0 * 100 <= stats.max_hp * HEAL_HEALTH_PERCENT;
+        let loosed = standoff.and_then(|target| {
+            let occupied: Vec<Position> = crowd_query.iter().copied().chain([target]).collect();
+            ranged_action((&map, &blockers), (*pos, target), wounded, kit, &spells, &occupied)
+        });
+        let action = match (loosed, dir) {
+            (Some(action), _) => action,
+            (None, Some(dir)) if movement == MovementType::Tunnel && map.is_diggable(pos.offset(dir)) => {
+                GameAction::Dig(pos.offset(dir))
+            }
+            (None, Some(dir)) => {
+                let target = pos.offset(dir);
+                let hostile = player.filter(|(_, player_pos)| **player_pos == target).map(|(player, _)| player);
+                bump_action(&map, *pos, dir, hostile)
+            }
+            // Cornered monsters lash out at an adjacent player
+            (None, None) => match player {
+                Some((player, player_pos)) if seen.is_some() && pos.is_adjacent(*player_pos) => {
+                    GameAction::Attack(player)
+                }
+                _ => GameAction::Wait,
+            },
+        };
+        action_events.send(ActionEvent { actor: entity, action });
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    components::{AwareOfPlayer, BlocksSight, Enemy, Energy, Health, LevelEntity, Player, PlayerClass, Position, RenderLayer},
+    data::{PendingData, RonAssetLoader},
+    dice::{Dice, DiceRoll},
+    fov::has_line_of_sight,
+    grid::{ATLAS_CELL_SIZE, TILE_SIZE},
+    hud::MessageLog,
+    items::{spawn_item, ItemKind, Scroll},
+    map::DungeonMap,
+    monsters::{Loot, LootItem},
+    projectile::{flight_path, spawn_projectile, Projectile, ProjectileKind},
+    spells::Spells,
+    stats::{DerivedStats, Stats},
+    AppState, CORPSE_INDEX,
+};
+
+/// Plugin that resolves attacks and removes creatures that run out of health.
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<CombatData>()
+            .register_asset_loader(RonAssetLoader::<CombatData>::new(&["combat.ron"]))
+            .add_event::<CombatResult>()
+            .add_systems(Startup, load_combat_data)
+            .add_systems(
+                Update,
+                (
+                    (resolve_melee_attacks, resolve_ranged_attacks).in_set(ActionSet),
+                    (apply_combat_results, handle_deaths).chain().after(ActionSet),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Tuning knobs for attack resolution.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CombatRules {
+    /// Rolled and added to the attacker's accuracy to hit.
+    pub to_hit: Dice,
+    /// Target number to hit before the defender's evasion is added.
+    pub base_target: i32,
+    /// Natural to-hit rolls at or above this always hit, and crit.
+    pub crit_threshold: i32,
+    /// Natural to-hit rolls at or below this always miss.
+    pub fumble_threshold: i32,
+    /// How many times a crit rolls the weapon dice.
+    pub crit_multiplier: u32,
+    /// To-hit bonus against monsters that haven't noticed the attacker.
+    pub sneak_to_hit: i32,
+    /// Extra damage against monsters that haven't noticed the attacker.
+    pub sneak_damage: Dice,
+}
+
+/// The attack rules and each class's starting weapons, as read from `combat.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct CombatData {
+    pub rules: CombatRules,
+    pub classes: HashMap<PlayerClass, ClassWeapons>,
+}
+
+/// What a class starts the game wielding.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ClassWeapons {
+    pub melee: AttackProfile,
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+}
+
+#[derive(Resource)]
+pub struct CombatDataHandle(pub Handle<CombatData>);
+
+/// Read access to the loaded combat data.
+#[derive(SystemParam)]
+pub struct Combat<'w> {
+    data: Res<'w, Assets<CombatData>>,
+    handle: Res<'w, CombatDataHandle>,
+}
+
+impl Combat<'_> {
+    /// Only call once the game has started: `AppState::Loading` waits for `combat.ron`.
+    pub fn rules(&self) -> &CombatRules {
+        &self.data().rules
+    }
+
+    pub fn weapons(&self, class: PlayerClass) -> ClassWeapons {
+        *self
+            .data()
+            .classes
+            .get(&class)
+            .expect("combat.ron gives every class its weapons")
+    }
+
+    fn data(&self) -> &CombatData {
+        self.data
+            .get(&self.handle.0)
+            .expect("combat.ron is loaded before the game starts")
+    }
+}
+
+fn load_combat_data(mut commands: Commands, asset_server: Res<AssetServer>, mut pending: ResMut<PendingData>) {
+    let handle = asset_server.load("combat.ron");
+    pending.0.push(handle.clone().untyped());
+    commands.insert_resource(CombatDataHandle(handle));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum DamageType {
+    Slash,
+    Pierce,
+    Blunt,
+    Fire,
+    Cold,
+    Poison,
+    Holy,
+    Unholy,
+}
+
+impl DamageType {
+    pub fn name(self) -> &'static str {
+        match self {
+            DamageType::Slash => "slashing",
+            DamageType::Pierce => "piercing",
+            DamageType::Blunt => "blunt",
+            DamageType::Fire => "fire",
+            DamageType::Cold => "cold",
+            DamageType::Poison => "poison",
+            DamageType::Holy => "holy",
+            DamageType::Unholy => "unholy",
+        }
+    }
+}
+
+/// What an attack does on a hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct AttackProfile {
+    pub damage: Dice,
+    pub damage_type: DamageType,
+}
+
+/// A creature's bare-handed or wielded melee attack.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MeleeAttack(pub AttackProfile);
+
+/// A bow, crossbow or wand the creature can fire at targets up to `range` cells away.
+#[derive(Component, Debug, Clone, Copy, Deserialize)]
+pub struct RangedAttack {
+    pub attack: AttackProfile,
+    pub range: i32,
+}
+
+/// Percent of each damage type a creature shrugs off; negative values are vulnerabilities and 100 is immunity.
+#[derive(Component, Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Resistances(pub HashMap<DamageType, i32>);
+
+impl Resistances {
+    pub fn percent(&self, damage_type: DamageType) -> i32 {
+        self.0.get(&damage_type).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitOutcome {
+    Fumble,
+    Miss,
+    Hit,
+    Critical,
+}
+
+/// Every roll behind one attack, sent so damage and the message log can be applied separately.
+#[derive(Event, Debug, Clone)]
+pub struct CombatResult {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub to_hit: DiceRoll,
+    pub target_number: i32,
+    pub outcome: HitOutcome,
+    pub sneak_attack: bool,
+    pub damage_rolls: Vec<DiceRoll>,
+    pub damage_type: DamageType,
+    /// The target's resistance to `damage_type` in percent.
+    pub resistance: i32,
+    pub damage_reduction: i32,
+    pub damage: i32,
+}
+
+/// Rolls one attack of `weapon` from `attacker` against `target`.
+pub fn roll_attack(
+    rng: &mut impl Rng,
+    rules: &CombatRules,
+    (attacker, attacker_stats): (Entity, &Stats),
+    (target, target_stats, resistances): (Entity, &Stats, Option<&Resistances>),
+    weapon: AttackProfile,
+    sneak_attack: bool,
+) -> CombatResult {
+    let mut to_hit = rules.to_hit.roll(rng);
+    to_hit.bonus += attacker_stats.accuracy;
+    if sneak_attack {
+        to_hit.bonus += rules.sneak_to_hit;
+    }
+    let target_number = rules.base_target + target_stats.evasion;
+
+    let outcome = match to_hit.natural() {
+        natural if natural <= rules.fumble_threshold => HitOutcome::Fumble,
+        natural if natural >= rules.crit_threshold => HitOutcome::Critical,
+        _ if to_hit.total() >= target_number => HitOutcome::Hit,
+        _ => HitOutcome::Miss,
+    };
+
+    let mut damage_rolls = Vec::new();
+    if matches!(outcome, HitOutcome::Hit | HitOutcome::Critical) {
+        let times = if outcome == HitOutcome::Critical { rules.crit_multiplier } else { 1 };
+        for _ in 0..times {
+            damage_rolls.push(weapon.damage.roll(rng));
+        }
+        damage_rolls[0].bonus += attacker_stats.attack;
+        if sneak_attack {
+            damage_rolls.push(rules.sneak_damage.roll(rng));
+        }
+    }
+
+    // Resistance scales the blow, then armour soaks up part of it; anything not immune always takes a little
+    let resistance = resistances.map_or(0, |r| r.percent(weapon.damage_type));
+    let damage_reduction = if damage_rolls.is_empty() { 0 } else { target_stats.defense };
+    let damage = if damage_rolls.is_empty() || resistance >= 100 {
+        0
+    } else {
+        let raw = damage_rolls.iter().map(DiceRoll::total).sum::<i32>();
+        (raw * (100 - resistance) / 100 - damage_reduction).max(1)
+    };
+
+    CombatResult {
+        attacker,
+        target,
+        to_hit,
+        target_number,
+        outcome,
+        sneak_attack,
+        damage_rolls,
+        damage_type: weapon.damage_type,
+        resistance,
+        damage_reduction,
+        damage,
+    }
+}
+
+/// Display name for log messages; the player is always "you".
+fn name_of(entity: Entity, names: &Query<&Name>) -> String {
+    names
+        .get(entity)
+        .map_or_else(|_| "something".to_string(), |name| name.as_str().to_string())
+}
+
+pub fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// One log line explaining an attack, e.g. "You hit the orc for 7 slashing ([14]+2 vs 11; [3,4]+2 -1)".
+fn describe(result: &CombatResult, attacker_is_player: bool, names: &Query<&Name>) -> String {
+    let attacker = capitalize(&name_of(result.attacker, names));
+    let target = name_of(result.target, names);
+    let verb = |player: &'static str, other: &'static str| if attacker_is_player { player } else { other };
+    let to_hit = format!("{} vs {}", result.to_hit, result.target_number);
+
+    match result.outcome {
+        HitOutcome::Fumble => format!("{attacker} {} {target} badly ({to_hit}).", verb("miss", "misses")),
+        HitOutcome::Miss => format!("{attacker} {} {target} ({to_hit}).", verb("miss", "misses")),
+        HitOutcome::Hit | HitOutcome::Critical => {
+            let rolls: Vec<String> = result.damage_rolls.iter().map(DiceRoll::to_string).collect();
+            let soak = match result.damage_reduction {
+                0 => String::new(),
+                soak => format!(" -{soak}"),
+            };
+            let critically = if result.outcome == HitOutcome::Critical { "critically " } else { "" };
+            let unseen = if result.sneak_attack { " from the shadows" } else { "" };
+            let affinity = match result.resistance {
+                r if r >= 100 => " immune",
+                r if r > 0 => " resisted",
+                r if r < 0 => " vulnerable",
+                _ => "",
+            };
+            format!(
+                "{attacker} {critically}{} {target}{unseen} for {} {} ({to_hit}; {}{affinity}{soak}).",
+                verb("hit", "hits"),
+                result.damage,
+                result.damage_type.name(),
+                rolls.join(" + "),
+            )
+        }
+    }
+}
+
+type AttackerData<'a> = (&'a Position, &'a DerivedStats, &'a MeleeAttack, &'a mut Energy, Has<Player>);
+type DefenderData<'a> = (&'a Position, &'a DerivedStats, Option<&'a Resistances>, Has<Enemy>, Has<AwareOfPlayer>);
+
+fn resolve_melee_attacks(
+    mut commands: Commands,
+    combat: Combat,
+    mut action_events: EventReader<ActionEvent>,
+    mut attacker_query: Query<AttackerData>,
+    target_query: Query<DefenderData, With<Health>>,
+    mut result_events: EventWriter<CombatResult>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for event in action_events.read() {
+        let GameAction::Attack(target) = event.action else {
+            continue;
+        };
+        let Ok((pos, DerivedStats(stats), attack, mut energy, is_player)) = attacker_query.get_mut(event.actor)
+        else {
+            continue;
+        };
+        let Ok((target_pos, DerivedStats(target_stats), resistances, is_enemy, aware)) = target_query.get(target)
+        else {
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        };
+        if target == event.actor || !pos.is_adjacent(*target_pos) {
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        }
+
+        let sneak_attack = is_player && is_enemy && !aware;
+        result_events.send(roll_attack(
+            &mut rng,
+            combat.rules(),
+            (event.actor, stats),
+            (target, target_stats, resistances),
+            attack.0,
+            sneak_attack,
+        ));
+        settle(&mut commands, event.actor, &mut energy, is_player, true);
+    }
+}
+
+/// Shots leave as projectiles that fly past the aimed cell until they hit something.
+fn resolve_ranged_attacks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    map: Res<DungeonMap>,
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    mut action_events: EventReader<ActionEvent>,
+    mut shooter_query: Query<(&Position, &DerivedStats, &RangedAttack, &mut Energy, Has<Player>)>,
+) {
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+    for event in action_events.read() {
+        let GameAction::Shoot(aim) = event.action else {
+            continue;
+        };
+        let Ok((pos, DerivedStats(stats), ranged, mut energy, is_player)) = shooter_query.get_mut(event.actor)
+        else {
+            continue;
+        };
+        let in_range = (aim.x - pos.x).abs().max((aim.y - pos.y).abs()) <= ranged.range;
+        if aim == *pos || !in_range || !has_line_of_sight(&map, &blockers, *pos, aim) {
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        }
+
+        spawn_projectile(
+            &mut commands,
+            &asset_server,
+            &mut texture_atlas_layouts,
+            ProjectileKind::Arrow,
+            *pos,
+            Projectile::new(
+                (event.actor, is_player),
+                *stats,
+                ranged.attack,
+                flight_path(*pos, aim, ranged.range, false),
+                0,
+            ),
+        );
+        settle(&mut commands, event.actor, &mut energy, is_player, true);
+    }
+}
+
+fn apply_combat_results(
+    mut result_events: EventReader<CombatResult>,
+    mut health_query: Query<&mut Health>,
+    player_query: Query<(), With<Player>>,
+    names: Query<&Name>,
+    mut log: ResMut<MessageLog>,
+) {
+    for result in result_events.read() {
+        log.push(describe(result, player_query.contains(result.attacker), &names));
+        if result.damage > 0
+            && let Ok(mut health) = health_query.get_mut(result.target)
+        {
+            health.0 -= result.damage;
+        }
+    }
+}
+
+type DeadData<'a> = (Entity, &'a Health, &'a Position, Option<&'a Loot>, Has<Player>);
+
+/// Slain creatures leave a corpse and monsters whatever their loot table rolls; the player's death ends the game.
+#[allow(clippy::too_many_arguments)]
+fn handle_deaths(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    spells: Spells,
+    dead_query: Query<DeadData, Changed<Health>>,
+    names: Query<&Name>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, health, pos, loot, is_player) in &dead_query {
+        if health.0 > 0 {
+            continue;
+        }
+        commands.entity(entity).despawn_recursive();
+
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 17, 26, None, None);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..default()
+                },
+                texture: asset_server.load("tiles.png"),
+                transform: Transform::from_translation(pos.to_world(RenderLayer::Decal.z())),
+                ..default()
+            },
+            TextureAtlas {
+                layout: texture_atlas_layouts.add(layout),
+                index: CORPSE_INDEX,
+            },
+            *pos,
+            RenderLayer::Decal,
+            LevelEntity,
+        ));
+
+        if is_player {
+            log.push("You die...");
+            next_state.set(AppState::GameOver);
+            continue;
+        }
+        log.push(format!("{} dies.", capitalize(&name_of(entity, &names))));
+
+        for drop in loot.iter().flat_map(|loot| &loot.0) {
+            if !rng.gen_bool(drop.chance.clamp(0.0, 1.0)) {
+                continue;
+            }
+            let kind = match &drop.item {
+                LootItem::Gold(dice) => ItemKind::Gold(dice.roll(&mut rng).total().max(1) as u32),
+                LootItem::Book(id) | LootItem::Scroll(id) => {
+                    let Some(spell) = spells.registry().find(id) else {
+                        continue;
+                    };
+                    let Some(def) = spells.get(spell) else {
+                        continue;
+                    };
+                    match drop.item {
+                        LootItem::Scroll(_) => ItemKind::Scroll(Scroll::Spell(spell)),
+                        _ => ItemKind::Book { spell, icon: def.icon },
+                    }
+                }
+            };
+            spawn_item(&mut commands, &asset_server, &mut texture_atlas_layouts, kind, *pos);
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 #[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct RoomId(pub usize);
@@ -15,13 +18,40 @@ pub struct Position {
     pub y: i32,
 }
 
+/// Draw order bucket; the transform sync turns this into a z value so layering stays consistent.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderLayer {
+    Floor,
+    Decal,
+    Furniture,
+    Item,
+    Creature,
+    Effect,
+    Overlay,
+}
+
+impl RenderLayer {
+    pub fn z(self) -> f32 {
+        match self {
+            RenderLayer::Floor => 0.0,
+            RenderLayer::Decal => 1.0,
+            RenderLayer::Furniture => 2.0,
+            RenderLayer::Item => 3.0,
+            RenderLayer::Creature => 4.0,
+            RenderLayer::Effect => 5.0,
+            RenderLayer::Overlay => 6.0,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Player;
 
+/// Belongs to the current level and is despawned when the player leaves it.
 #[derive(Component)]
-pub struct Wall;
+pub struct LevelEntity;
 
-#[derive(Debug, Clone, Copy, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component, Deserialize)]
 pub enum PlayerClass {
     Warrior,
     Mage,
@@ -31,5 +61,55 @@ pub enum PlayerClass {
 #[derive(Component)]
 pub struct Enemy;
 
+/// Cells this entity can currently see, recomputed whenever it moves or the map changes.
+#[derive(Component, Default)]
+pub struct Viewshed {
+    pub range: i32,
+    pub visible_tiles: HashSet<Position>,
+}
+
+/// Entities such as doors and tall furniture that block line of sight through their cell.
+#[derive(Component)]
+pub struct BlocksSight;
+
+/// Emits light that the lighting pass spreads over the tiles in its field of view.
+#[derive(Component, Clone, Copy, Debug, Deserialize)]
+pub struct LightSource {
+    pub radius: i32,
+    pub intensity: f32,
+}
+
+/// Monsters that currently have the player in view.
+#[derive(Component)]
+pub struct AwareOfPlayer;
+
+/// Accumulated action points; an actor may act once this reaches the action cost.
+#[derive(Component)]
+pub struct Energy(pub i32);
+
+/// Energy gained per scheduler tick.
+#[derive(Component)]
+pub struct Speed(pub i32);
+
+/// Marks the actor whose turn it currently is.
+#[derive(Component)]
+pub struct TakingTurn;
+
+/// Query filter for the player while it is their turn.
+pub type PlayersTurn = (With<Player>, With<TakingTurn>);
+
+/// Lets an entity break walls; `power` is dig effort applied per action.
+#[derive(Component, Clone, Copy)]
+pub struct Digger {
+    pub power: u32,
+}
+
 #[derive(Component)]
-pub struct Health(pub i32);
\ No newline at end of file
+pub struct Health(pub i32);
+
+/// Spell points; the pool grows with the creature's magic.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Mana {
+    pub current: i32,
+    pub max: i32,
+}
@@ -0,0 +1,103 @@
+use std::{fmt, marker::PhantomData};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
+    prelude::*,
+};
+use serde::de::DeserializeOwned;
+
+use crate::AppState;
+
+/// Plugin that holds the game in [`AppState::Loading`] until every data file has loaded.
+pub struct DataPlugin;
+
+impl Plugin for DataPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingData>()
+            .add_systems(Update, wait_for_data.run_if(in_state(AppState::Loading)));
+    }
+}
+
+/// Data files the game can't start without; each loader adds its handle at startup.
+#[derive(Resource, Default)]
+pub struct PendingData(pub Vec<UntypedHandle>);
+
+fn wait_for_data(
+    asset_server: Res<AssetServer>,
+    pending: Res<PendingData>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for handle in &pending.0 {
+        if let Some(LoadState::Failed(err)) = asset_server.get_load_state(handle) {
+            panic!("could not load game data: {err}");
+        }
+    }
+    if pending.0.iter().all(|handle| asset_server.is_loaded_with_dependencies(handle)) {
+        next_state.set(AppState::InGame);
+    }
+}
+
+/// Loads a game data asset from a RON file, e.g. `spells.ron`.
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _asset: PhantomData<fn() -> A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonAssetLoader {
+            extensions,
+            _asset: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RonLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for RonLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RonLoaderError::Io(err) => write!(f, "could not read data file: {err}"),
+            RonLoaderError::Ron(err) => write!(f, "could not parse data file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RonLoaderError {}
+
+impl From<std::io::Error> for RonLoaderError {
+    fn from(err: std::io::Error) -> Self {
+        RonLoaderError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for RonLoaderError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        RonLoaderError::Ron(err)
+    }
+}
+
+impl<A: Asset + DeserializeOwned> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<A, RonLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
@@ -0,0 +1,159 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+use serde::Deserialize;
+
+/// A dice expression such as `2d6+3`; data files write it as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Dice {
+    pub count: u32,
+    pub sides: u32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub const fn new(count: u32, sides: u32, bonus: i32) -> Self {
+        Dice { count, sides, bonus }
+    }
+
+    /// The lowest total the dice can roll.
+    pub fn min(&self) -> i32 {
+        self.count as i32 + self.bonus
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> DiceRoll {
+        DiceRoll {
+            rolls: (0..self.count)
+                .map(|_| rng.gen_range(1..=self.sides.max(1)) as i32)
+                .collect(),
+            bonus: self.bonus,
+        }
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.bonus {
+            0 => Ok(()),
+            bonus if bonus > 0 => write!(f, "+{bonus}"),
+            bonus => write!(f, "{bonus}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiceError(String);
+
+impl fmt::Display for ParseDiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid dice expression `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseDiceError {}
+
+/// Parses `NdS`, `dS`, `NdS+B`, `NdS-B` or a flat number.
+impl FromStr for Dice {
+    type Err = ParseDiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDiceError(s.to_string());
+        let text: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+
+        let Some((count, rest)) = text.split_once('d') else {
+            let bonus = text.parse().map_err(|_| err())?;
+            return Ok(Dice::new(0, 0, bonus));
+        };
+        let count = if count.is_empty() { 1 } else { count.parse().map_err(|_| err())? };
+        let (sides, bonus) = match rest.find(['+', '-']) {
+            Some(split) => (&rest[..split], rest[split..].parse().map_err(|_| err())?),
+            None => (rest, 0),
+        };
+        let sides = sides.parse().map_err(|_| err())?;
+        if sides == 0 {
+            return Err(err());
+        }
+        Ok(Dice::new(count, sides, bonus))
+    }
+}
+
+impl TryFrom<String> for Dice {
+    type Error = ParseDiceError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The individual dice that came up, plus the flat bonus added to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceRoll {
+    pub rolls: Vec<i32>,
+    pub bonus: i32,
+}
+
+impl DiceRoll {
+    /// Sum of the dice alone.
+    pub fn natural(&self) -> i32 {
+        self.rolls.iter().sum()
+    }
+
+    pub fn total(&self) -> i32 {
+        self.natural() + self.bonus
+    }
+}
+
+impl fmt::Display for DiceRoll {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rolls: Vec<String> = self.rolls.iter().map(i32::to_string).collect();
+        write!(f, "[{}]", rolls.join(","))?;
+        match self.bonus {
+            0 => Ok(()),
+            bonus if bonus > 0 => write!(f, "+{bonus}"),
+            bonus => write!(f, "{bonus}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn parses_single_die() {
+        assert_eq!("1d6".parse(), Ok(Dice::new(1, 6, 0)));
+    }
+
+    #[test]
+    fn parses_bonus() {
+        assert_eq!("2d4+1".parse(), Ok(Dice::new(2, 4, 1)));
+    }
+
+    #[test]
+    fn parses_flat_number() {
+        assert_eq!("3".parse(), Ok(Dice::new(0, 0, 3)));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for bad in ["", "d", "2d", "1d0", "xd6", "1d6+", "one"] {
+            assert!(bad.parse::<Dice>().is_err(), "{bad:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn rolls_stay_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for dice in [Dice::new(1, 6, 0), Dice::new(2, 4, 1), Dice::new(3, 8, -2), Dice::new(0, 0, 3)] {
+            let max = (dice.count * dice.sides) as i32 + dice.bonus;
+            for _ in 0..1000 {
+                let total = dice.roll(&mut rng).total();
+                assert!((dice.min()..=max).contains(&total), "{dice} rolled {total}");
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::Position,
+    map::{DungeonMap, Tile, TileChanged},
+    AppState,
+};
+
+/// How far a mage's blast reaches, and the hardest wall it can shatter.
+pub const BLAST_RADIUS: i32 = 1;
+pub const BLAST_POWER: u32 = 4;
+
+/// Plugin that lets walls be dug out or blasted apart, updating the tile grid as they fall.
+pub struct DigPlugin;
+
+impl Plugin for DigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DigEvent>()
+            .add_event::<BlastEvent>()
+            .add_systems(
+                Update,
+                resolve_digs.run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// One dig action against a single wall tile.
+#[derive(Event)]
+pub struct DigEvent {
+    pub target: Position,
+    pub power: u32,
+}
+
+/// Destroys every wall within `radius` whose hardness does not exceed `power`.
+#[derive(Event)]
+pub struct BlastEvent {
+    pub center: Position,
+    pub radius: i32,
+    pub power: u32,
+}
+
+fn resolve_digs(
+    mut map: ResMut<DungeonMap>,
+    mut dig_events: EventReader<DigEvent>,
+    mut blast_events: EventReader<BlastEvent>,
+    mut changed_events: EventWriter<TileChanged>,
+) {
+    let mut broken = Vec::new();
+
+    for dig in dig_events.read() {
+        if map.apply_dig(dig.target, dig.power) {
+            broken.push(dig.target);
+        }
+    }
+
+    for blast in blast_events.read() {
+        for dy in -blast.radius..=blast.radius {
+            for dx in -blast.radius..=blast.radius {
+                let pos = Position {
+                    x: blast.center.x + dx,
+                    y: blast.center.y + dy,
+                };
+                if let Tile::Wall(material) = map.tile(pos)
+                    && map.is_diggable(pos)
+                    && material.hardness() <= blast.power
+                {
+                    broken.push(pos);
+                }
+            }
+        }
+    }
+
+    for pos in broken {
+        let Tile::Wall(material) = map.tile(pos) else {
+            continue;
+        };
+        map.set_tile(pos, Tile::Floor);
+        changed_events.send(TileChanged { pos });
+
+        // Keep the new opening enclosed by rock of the same material
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let neighbor = Position {
+                    x: pos.x + dx,
+                    y: pos.y + dy,
+                };
+                if map.in_bounds(neighbor) && map.tile(neighbor) == Tile::Void {
+                    map.set_tile(neighbor, Tile::Wall(material));
+                    changed_events.send(TileChanged { pos: neighbor });
+                }
+            }
+        }
+    }
+}
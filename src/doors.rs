@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    components::{Energy, Player, Position},
+    map::{DungeonMap, Room, Tile, TileChanged},
+    AppState,
+};
+
+/// Plugin for the doors between rooms and corridors, and opening them.
+pub struct DoorsPlugin;
+
+impl Plugin for DoorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            resolve_open
+                .in_set(ActionSet)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// Hangs shut doors where a corridor breaks through a room's wall line.
+pub fn hang_doors(map: &mut DungeonMap, rooms: &[Room], rng: &mut impl Rng) {
+    for room in rooms {
+        let r = room.inner;
+        for y in r.y - 1..=r.y + r.height {
+            for x in r.x - 1..=r.x + r.width {
+                let on_column = x == r.x - 1 || x == r.x + r.width;
+                let on_row = y == r.y - 1 || y == r.y + r.height;
+                let pos = Position { x, y };
+                if on_column == on_row || map.tile(pos) != Tile::Floor || map.room_at(pos).is_some() {
+                    continue;
+                }
+                // Only single-width openings get a door
+                let along = if on_row { IVec2::X } else { IVec2::Y };
+                if !map.is_floor(pos.offset(along)) && !map.is_floor(pos.offset(-along)) && rng.gen_bool(0.5) {
+                    map.set_tile(pos, Tile::Door { open: false });
+                }
+            }
+        }
+    }
+}
+
+fn resolve_open(
+    mut commands: Commands,
+    mut map: ResMut<DungeonMap>,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<(&Position, &mut Energy, Has<Player>)>,
+    mut changed_events: EventWriter<TileChanged>,
+) {
+    for event in action_events.read() {
+        let GameAction::Open(target) = event.action else {
+            continue;
+        };
+        let Ok((pos, mut energy, is_player)) = actor_query.get_mut(event.actor) else {
+            continue;
+        };
+
+        let succeeded = pos.is_adjacent(target) && map.tile(target) == Tile::Door { open: false };
+        if succeeded {
+            map.set_tile(target, Tile::Door { open: true });
+            changed_events.send(TileChanged { pos: target });
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
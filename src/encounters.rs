@@ -0,0 +1,382 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    Rng,
+};
+use serde::Deserialize;
+
+use crate::{
+    actions::ActionSet,
+    ai::{AiState, Pack, PackMember, Tactic},
+    components::{Health, LevelEntity, Player, Position},
+    data::{PendingData, RonAssetLoader},
+    dice::Dice,
+    map::DungeonMap,
+    monsters::{spawn_monster, MonsterDef, MonsterRegistry, Monsters},
+    pathfinding::STEPS,
+    spells::{SpellRegistry, Spells},
+    stairs::Depth,
+    turn::GameTime,
+    AppState,
+};
+
+/// Difficulty spent on the monsters of a level: a base plus a share for every level down.
+const BASE_BUDGET: u32 = 4;
+const BUDGET_PER_DEPTH: u32 = 3;
+/// Chance that a level also holds one group from further down.
+const OUT_OF_DEPTH_CHANCE: f64 = 0.1;
+/// How much deeper such a group comes from.
+const OUT_OF_DEPTH_LEVELS: u32 = 3;
+/// Closest, in steps, that a group is placed to the player.
+pub const MIN_ENCOUNTER_DISTANCE: u32 = 6;
+
+/// Turns between checks for a wandering group turning up.
+const WANDER_CHECK_TURNS: u64 = 20;
+/// Chance of a wandering group at each check on arrival, growing with every hundred turns spent on the level.
+const WANDER_BASE_CHANCE: f64 = 0.05;
+const WANDER_CHANCE_PER_100_TURNS: f64 = 0.05;
+const WANDER_MAX_CHANCE: f64 = 0.5;
+/// Wandering groups are sized at this fraction of a level's budget.
+const WANDER_BUDGET_DIVISOR: u32 = 3;
+
+/// Plugin for the encounter tables in `assets/encounters.ron`.
+pub struct EncountersPlugin;
+
+impl Plugin for EncountersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EncounterTable>()
+            .register_asset_loader(RonAssetLoader::<EncounterTable>::new(&["encounters.ron"]))
+            .add_systems(Startup, load_encounters)
+            .add_systems(
+                Update,
+                spawn_wanderers
+                    .after(ActionSet)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Who a level mostly belongs to, which decides the groups found there.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LevelTheme {
+    /// Goblins, orcs and their hangers-on.
+    Warren,
+    /// The restless dead and those who serve them.
+    Crypt,
+    /// Vermin and beasts.
+    Lair,
+}
+
+impl LevelTheme {
+    pub const ALL: [LevelTheme; 3] = [LevelTheme::Warren, LevelTheme::Crypt, LevelTheme::Lair];
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupMember {
+    /// Id of a monster in `monsters.ron`.
+    pub monster: String,
+    pub count: Dice,
+    /// Heads the group; such a member should come alone.
+    #[serde(default)]
+    pub leader: bool,
+}
+
+/// Monsters that turn up together. A group only appears where all of its members do.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EncounterGroup {
+    pub members: Vec<GroupMember>,
+    /// How often it comes up compared to the other groups that fit.
+    pub weight: u32,
+    /// Themes it belongs to; any level will do if empty.
+    #[serde(default)]
+    pub themes: Vec<LevelTheme>,
+    #[serde(default)]
+    pub tactic: Tactic,
+}
+
+/// A group rolled up for a level, its leader first if it has one.
+pub struct Encounter<'a> {
+    pub members: Vec<&'a MonsterDef>,
+    pub tactic: Tactic,
+    pub led: bool,
+}
+
+fn level_budget(depth: u32) -> u32 {
+    BASE_BUDGET + BUDGET_PER_DEPTH * depth
+}
+
+fn cost(members: &[&MonsterDef]) -> u32 {
+    members.iter().map(|def| def.difficulty.max(1)).sum()
+}
+
+impl EncounterGroup {
+    fn fits(&self, monsters: &MonsterRegistry, depth: u32, theme: LevelTheme) -> bool {
+        (self.themes.is_empty() || self.themes.contains(&theme))
+            && self
+                .members
+                .iter()
+                .all(|member| monsters.find(&member.monster).is_some_and(|def| def.found_at(depth)))
+    }
+
+    /// The least the group can cost, with every count rolling low.
+    fn min_cost(&self, monsters: &MonsterRegistry) -> u32 {
+        self.members
+            .iter()
+            .filter_map(|member| {
+                let def = monsters.find(&member.monster)?;
+                Some(def.difficulty.max(1) * member.count.min().max(0) as u32)
+            })
+            .sum::<u32>()
+            .max(1)
+    }
+
+    /// Rolls how many of each member show up; there is always at least one.
+    fn roll<'a>(&self, monsters: &'a MonsterRegistry, rng: &mut impl Rng) -> Encounter<'a> {
+        let mut rolled: Vec<&MonsterDef> = Vec::new();
+        let mut led = false;
+        let (leaders, followers): (Vec<_>, Vec<_>) = self.members.iter().partition(|member| member.leader);
+        for member in leaders.into_iter().chain(followers) {
+            if let Some(def) = monsters.find(&member.monster) {
+                let count = member.count.roll(rng).total().max(0) as usize;
+                led |= member.leader && count > 0 && rolled.is_empty();
+                rolled.extend(std::iter::repeat_n(def, count));
+            }
+        }
+        if rolled.is_empty()
+            && let Some(def) = self.members.first().and_then(|member| monsters.find(&member.monster))
+        {
+            rolled.push(def);
+        }
+        Encounter {
+            members: rolled,
+            tactic: self.tactic,
+            led,
+        }
+    }
+}
+
+/// Every encounter group, as read from `encounters.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct EncounterTable {
+    pub groups: Vec<EncounterGroup>,
+}
+
+impl EncounterTable {
+    /// Spends a level's difficulty budget on groups that fit it, rarely adding one from deeper down.
+    pub fn roll_level<'a>(
+        &self,
+        monsters: &'a MonsterRegistry,
+        depth: u32,
+        theme: LevelTheme,
+        rng: &mut impl Rng,
+    ) -> Vec<Encounter<'a>> {
+        let mut budget = level_budget(depth);
+        let mut encounters = Vec::new();
+
+        // Paid for out of the same budget, but allowed to blow it
+        if rng.gen_bool(OUT_OF_DEPTH_CHANCE)
+            && let Some(group) = self.pick(monsters, depth + OUT_OF_DEPTH_LEVELS, theme, u32::MAX, rng)
+        {
+            let encounter = group.roll(monsters, rng);
+            budget = budget.saturating_sub(cost(&encounter.members));
+            encounters.push(encounter);
+        }
+
+        while let Some(encounter) = self.roll_group(monsters, depth, theme, budget, rng) {
+            budget = budget.saturating_sub(cost(&encounter.members));
+            encounters.push(encounter);
+        }
+        encounters
+    }
+
+    /// A single group that fits the level, cut down to what `budget` can pay for.
+    pub fn roll_group<'a>(
+        &self,
+        monsters: &'a MonsterRegistry,
+        depth: u32,
+        theme: LevelTheme,
+        budget: u32,
+        rng: &mut impl Rng,
+    ) -> Option<Encounter<'a>> {
+        let mut encounter = self.pick(monsters, depth, theme, budget, rng)?.roll(monsters, rng);
+        // Whoever the budget doesn't stretch to stays home
+        while encounter.members.len() > 1 && cost(&encounter.members) > budget {
+            encounter.members.pop();
+        }
+        Some(encounter)
+    }
+
+    /// A random group that fits the level and the budget, weighted.
+    fn pick(
+        &self,
+        monsters: &MonsterRegistry,
+        depth: u32,
+        theme: LevelTheme,
+        budget: u32,
+        rng: &mut impl Rng,
+    ) -> Option<&EncounterGroup> {
+        let fitting: Vec<&EncounterGroup> = self
+            .groups
+            .iter()
+            .filter(|group| group.fits(monsters, depth, theme) && group.min_cost(monsters) <= budget)
+            .collect();
+        fitting.choose_weighted(rng, |group| group.weight).ok().copied()
+    }
+}
+
+#[derive(Resource)]
+pub struct EncounterTableHandle(pub Handle<EncounterTable>);
+
+/// Read access to the loaded encounter table.
+#[derive(SystemParam)]
+pub struct Encounters<'w> {
+    tables: Res<'w, Assets<EncounterTable>>,
+    handle: Res<'w, EncounterTableHandle>,
+}
+
+impl Encounters<'_> {
+    /// Only call once the game has started: `AppState::Loading` waits for `encounters.ron`.
+    pub fn table(&self) -> &EncounterTable {
+        self.tables
+            .get(&self.handle.0)
+            .expect("encounters.ron is loaded before the game starts")
+    }
+}
+
+fn load_encounters(mut commands: Commands, asset_server: Res<AssetServer>, mut pending: ResMut<PendingData>) {
+    let handle = asset_server.load("encounters.ron");
+    pending.0.push(handle.clone().untyped());
+    commands.insert_resource(EncounterTableHandle(handle));
+}
+
+/// Free floor cells in order of how quickly they're reached from `anchor`, starting with it.
+pub fn gather_cells(map: &DungeonMap, anchor: Position, occupied: &HashSet<Position>) -> Vec<Position> {
+    let mut seen = HashSet::from([anchor]);
+    let mut queue = VecDeque::from([anchor]);
+    let mut cells = Vec::new();
+    while let Some(pos) = queue.pop_front() {
+        if !occupied.contains(&pos) {
+            cells.push(pos);
+        }
+        for step in STEPS {
+            let next = pos.offset(step);
+            if map.is_walkable(next) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    cells
+}
+
+/// Spawns a rolled group onto `cells` in order and, unless it fights loose, bands it into a pack.
+pub fn spawn_encounter(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    (encounter, spells): (Encounter, &SpellRegistry),
+    cells: impl IntoIterator<Item = Position>,
+    rng: &mut impl Rng,
+) -> Vec<(Entity, Position)> {
+    let spawned: Vec<(Entity, Position)> = encounter
+        .members
+        .into_iter()
+        .zip(cells)
+        .map(|(def, pos)| {
+            let monster = spawn_monster(commands, asset_server, texture_atlas_layouts, (def, spells), pos, rng);
+            (monster, pos)
+        })
+        .collect();
+
+    if encounter.tactic != Tactic::Loose
+        && let Some(&(first, home)) = spawned.first()
+    {
+        let pack = commands
+            .spawn((
+                Pack {
+                    tactic: encounter.tactic,
+                    leader: encounter.led.then_some(first),
+                    home,
+                },
+                LevelEntity,
+            ))
+            .id();
+        for (slot, (monster, _)) in spawned.iter().enumerate() {
+            commands.entity(*monster).insert(PackMember { pack, slot });
+        }
+    }
+    spawned
+}
+
+/// Turn the player arrived on the current level, and turn of the last wanderer check.
+#[derive(Default)]
+struct LevelClock {
+    arrived: u64,
+    checked: u64,
+}
+
+/// Now and then a group wanders in somewhere out of sight, more often the longer the player stays.
+#[allow(clippy::too_many_arguments)]
+fn spawn_wanderers(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    (time, depth, theme): (Res<GameTime>, Res<Depth>, Option<Res<LevelTheme>>),
+    map: Res<DungeonMap>,
+    player_query: Query<&Position, With<Player>>,
+    creature_query: Query<&Position, With<Health>>,
+    (monsters, encounters, spells): (Monsters, Encounters, Spells),
+    mut clock: Local<LevelClock>,
+) {
+    let turn = time.turn();
+    if depth.is_changed() {
+        *clock = LevelClock {
+            arrived: turn,
+            checked: turn,
+        };
+        return;
+    }
+    if turn < clock.checked + WANDER_CHECK_TURNS {
+        return;
+    }
+    clock.checked = turn;
+
+    let mut rng = rand::thread_rng();
+    let lingered = (turn - clock.arrived) as f64 / 100.0;
+    let chance = (WANDER_BASE_CHANCE + WANDER_CHANCE_PER_100_TURNS * lingered).min(WANDER_MAX_CHANCE);
+    if !rng.gen_bool(chance) {
+        return;
+    }
+    let (Some(theme), Ok(player_pos)) = (theme, player_query.get_single()) else {
+        return;
+    };
+    let budget = level_budget(depth.0) / WANDER_BUDGET_DIVISOR;
+    let Some(encounter) = encounters.table().roll_group(monsters.registry(), depth.0, *theme, budget, &mut rng) else {
+        return;
+    };
+
+    let occupied: HashSet<Position> = creature_query.iter().copied().collect();
+    let out_of_sight = |p: &Position| map.is_walkable(*p) && !map.is_visible(*p) && !occupied.contains(p);
+    let far_enough = |p: &Position| p.x.abs_diff(player_pos.x) + p.y.abs_diff(player_pos.y) >= MIN_ENCOUNTER_DISTANCE;
+    let Some(anchor) = map
+        .positions()
+        .filter(|p| out_of_sight(p) && far_enough(p))
+        .choose(&mut rng)
+    else {
+        return;
+    };
+    let cells = gather_cells(&map, anchor, &occupied).into_iter().filter(out_of_sight);
+    let spawned = spawn_encounter(
+        &mut commands,
+        &asset_server,
+        &mut texture_atlas_layouts,
+        (encounter, spells.registry()),
+        cells,
+        &mut rng,
+    );
+    // Wanderers arrive awake and on the move
+    for (monster, _) in spawned {
+        commands.entity(monster).insert(AiState::Wandering { goal: None });
+    }
+}
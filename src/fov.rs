@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    ai::AiState,
+    components::{AwareOfPlayer, BlocksSight, Enemy, Player, Position, RenderLayer, Viewshed},
+    grid::line_between,
+    lighting::{LightMap, NOTICE_LIGHT_THRESHOLD, SEE_LIGHT_THRESHOLD},
+    map::{DungeonMap, TileChanged, TileVisibility, TileVisibilityChanged},
+    AppState,
+};
+
+/// Plugin that recomputes field of view for every `Viewshed` and applies it to what the player can see.
+pub struct FovPlugin;
+
+/// Systems that turn the player's viewshed into visible tiles and monster awareness.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerVisibilitySet;
+
+impl Plugin for FovPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileVisibilityChanged>().add_systems(
+            Update,
+            (
+                update_viewsheds,
+                (update_visible_tiles, hide_unseen_sprites, update_monster_awareness)
+                    .chain()
+                    .in_set(PlayerVisibilitySet),
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// Every cell visible from `origin` within `range`, using symmetric shadowcasting.
+pub fn compute_fov(origin: Position, range: i32, is_opaque: impl Fn(Position) -> bool) -> HashSet<Position> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+
+    for quadrant in 0..4 {
+        let transform = |depth: i32, col: i32| match quadrant {
+            0 => Position { x: origin.x + col, y: origin.y + depth },
+            1 => Position { x: origin.x + depth, y: origin.y + col },
+            2 => Position { x: origin.x + col, y: origin.y - depth },
+            _ => Position { x: origin.x - depth, y: origin.y + col },
+        };
+
+        let mut rows = vec![(1, -1.0, 1.0)];
+        while let Some((depth, mut start_slope, end_slope)) = rows.pop() {
+            if depth > range {
+                continue;
+            }
+
+            let min_col = (depth as f64 * start_slope + 0.5).floor() as i32;
+            let max_col = (depth as f64 * end_slope - 0.5).ceil() as i32;
+            let mut prev_opaque = None;
+
+            for col in min_col..=max_col {
+                let pos = transform(depth, col);
+                let opaque = is_opaque(pos);
+                let symmetric = col as f64 >= depth as f64 * start_slope
+                    && col as f64 <= depth as f64 * end_slope;
+                let in_range = depth * depth + col * col <= range * range;
+
+                if in_range && (opaque || symmetric) {
+                    visible.insert(pos);
+                }
+
+                let slope = (2 * col - 1) as f64 / (2 * depth) as f64;
+                if prev_opaque == Some(true) && !opaque {
+                    start_slope = slope;
+                }
+                if prev_opaque == Some(false) && opaque {
+                    rows.push((depth + 1, start_slope, slope));
+                }
+                prev_opaque = Some(opaque);
+            }
+
+            if prev_opaque == Some(false) {
+                rows.push((depth + 1, start_slope, end_slope));
+            }
+        }
+    }
+
+    visible
+}
+
+/// Whether nothing on the straight line between two cells blocks sight, neither the map nor one of `blockers`;
+/// the ends themselves may be opaque.
+pub fn has_line_of_sight(map: &DungeonMap, blockers: &HashSet<Position>, from: Position, to: Position) -> bool {
+    let line = line_between(from, to);
+    let between = line.len().saturating_sub(2);
+    line.iter()
+        .skip(1)
+        .take(between)
+        .all(|p| !map.blocks_sight(*p) && !blockers.contains(p))
+}
+
+fn update_viewsheds(
+    map: Res<DungeonMap>,
+    mut changed_events: EventReader<TileChanged>,
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    mut viewer_query: Query<(Ref<Position>, &mut Viewshed)>,
+) {
+    let map_changed = changed_events.read().count() > 0;
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+
+    for (pos, mut viewshed) in &mut viewer_query {
+        if !map_changed && !pos.is_changed() {
+            continue;
+        }
+        viewshed.visible_tiles = compute_fov(*pos, viewshed.range, |p| {
+            map.blocks_sight(p) || blockers.contains(&p)
+        });
+    }
+}
+
+/// Tiles in the player's viewshed are only seen if lit, or close enough to touch.
+fn update_visible_tiles(
+    mut map: ResMut<DungeonMap>,
+    light_map: Res<LightMap>,
+    player_query: Query<(&Position, Ref<Viewshed>), With<Player>>,
+    mut visibility_events: EventWriter<TileVisibilityChanged>,
+) {
+    let Ok((player_pos, viewshed)) = player_query.get_single() else {
+        return;
+    };
+    if !viewshed.is_changed() && !light_map.is_changed() {
+        return;
+    }
+
+    let sees = |pos: &Position| {
+        viewshed.visible_tiles.contains(pos)
+            && (light_map.light_at(*pos) >= SEE_LIGHT_THRESHOLD
+                || ((pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1))
+    };
+    let flipped: Vec<Position> = map
+        .positions()
+        .filter(|pos| map.is_visible(*pos) != sees(pos))
+        .collect();
+    for pos in flipped {
+        map.set_visible(pos, sees(&pos));
+        visibility_events.send(TileVisibilityChanged { pos });
+    }
+}
+
+/// Sprites only show on cells the player can see, except furniture, which stays on remembered cells.
+fn hide_unseen_sprites(
+    map: Res<DungeonMap>,
+    mut sprite_query: Query<(&Position, &RenderLayer, &mut Visibility), With<Sprite>>,
+) {
+    for (pos, layer, mut visibility) in &mut sprite_query {
+        let shown = match map.visibility(*pos) {
+            TileVisibility::Visible => true,
+            TileVisibility::Remembered => *layer == RenderLayer::Furniture,
+            TileVisibility::Unseen => false,
+        };
+        visibility.set_if_neq(if shown { Visibility::Inherited } else { Visibility::Hidden });
+    }
+}
+
+type WatcherData<'a> = (Entity, &'a Position, &'a Viewshed, Option<&'a AiState>, Has<AwareOfPlayer>);
+
+/// Monsters notice a player in their view unless the player keeps to the shadows at a distance; sleepers notice nothing.
+fn update_monster_awareness(
+    mut commands: Commands,
+    light_map: Res<LightMap>,
+    player_query: Query<&Position, With<Player>>,
+    enemy_query: Query<WatcherData, With<Enemy>>,
+) {
+    let Ok(player_pos) = player_query.get_single() else {
+        return;
+    };
+    let player_lit = light_map.light_at(*player_pos) >= NOTICE_LIGHT_THRESHOLD;
+
+    for (entity, pos, viewshed, state, aware) in &enemy_query {
+        let adjacent = (pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1;
+        let sees_player = state != Some(&AiState::Sleeping)
+            && viewshed.visible_tiles.contains(player_pos)
+            && (player_lit || adjacent);
+        if sees_player && !aware {
+            commands.entity(entity).insert(AwareOfPlayer);
+        } else if !sees_player && aware {
+            commands.entity(entity).remove::<AwareOfPlayer>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Tile, WallMaterial};
+
+    fn at(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn open_ground_is_seen_out_to_range() {
+        let visible = compute_fov(at(0, 0), 3, |_| false);
+
+        assert!(visible.contains(&at(0, 0)));
+        assert!(visible.contains(&at(3, 0)));
+        assert!(visible.contains(&at(-2, 2)));
+        assert!(!visible.contains(&at(4, 0)));
+        assert!(!visible.contains(&at(3, 3)));
+    }
+
+    #[test]
+    fn walls_are_seen_but_hide_what_is_behind_them() {
+        let wall = |p: Position| p.x == 2 && (-1..=1).contains(&p.y);
+        let visible = compute_fov(at(0, 0), 6, wall);
+
+        assert!(visible.contains(&at(2, 0)));
+        assert!(!visible.contains(&at(3, 0)));
+        assert!(!visible.contains(&at(5, 1)));
+        assert!(visible.contains(&at(0, 5)));
+    }
+
+    #[test]
+    fn sight_is_symmetric() {
+        let pillars = [at(2, 1), at(-1, 3), at(3, -2), at(-3, -1)];
+        let opaque = |p: Position| pillars.contains(&p);
+        let origin = at(0, 0);
+
+        for pos in compute_fov(origin, 5, opaque) {
+            if !opaque(pos) {
+                assert!(compute_fov(pos, 5, opaque).contains(&origin), "{pos:?} is seen but can't see back");
+            }
+        }
+    }
+
+    #[test]
+    fn blockers_cut_line_of_sight() {
+        let mut map = DungeonMap::new(7, 3);
+        for pos in map.positions().collect::<Vec<_>>() {
+            map.set_tile(pos, Tile::Floor);
+        }
+        let brazier = HashSet::from([at(3, 1)]);
+
+        assert!(has_line_of_sight(&map, &HashSet::new(), at(0, 1), at(6, 1)));
+        assert!(!has_line_of_sight(&map, &brazier, at(0, 1), at(6, 1)));
+        assert!(has_line_of_sight(&map, &brazier, at(0, 1), at(3, 1)));
+
+        map.set_tile(at(5, 1), Tile::Wall(WallMaterial::Stone));
+        assert!(!has_line_of_sight(&map, &HashSet::new(), at(0, 1), at(6, 1)));
+    }
+}
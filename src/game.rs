@@ -1,25 +1,39 @@
 use std::collections::HashSet;
 
 use bevy::prelude::*;
-use rand::Rng;
+use rand::{seq::IteratorRandom, Rng};
 
 use crate::{
-    components::*, map::{bsp_split, Rect, Room}, minimap::{ spawn_minimap_ui_tiles}, spawn_floor_tile, spawn_wall_tile, AppState, PlayerClass, SelectedClass, FLOOR_TILE_INDEX, MAP_HEIGHT, MAP_WIDTH, MINIMAP_LAYER
+    abilities::Abilities,
+    actions::ActionSet,
+    combat::{Combat, MeleeAttack},
+    components::*,
+    doors::hang_doors,
+    encounters::{gather_cells, spawn_encounter, EncounterTable, Encounters, LevelTheme, MIN_ENCOUNTER_DISTANCE},
+    grid::{ATLAS_CELL_SIZE, TILE_SIZE},
+    items::{spawn_item, Inventory, ItemKind, Scroll},
+    lighting::AnimatedTile,
+    map::{bsp_split, DungeonMap, Rect, Room, Tile, TileChanged, WallMaterial},
+    minimap::spawn_minimap_ui_tiles,
+    monsters::{MonsterRegistry, Monsters},
+    spells::{SpellId, SpellRegistry, Spellbook, Spells},
+    stairs::{place_stairs, Depth},
+    stats::{StatModifiers, Stats, MANA_PER_MAGIC},
+    turn::{GameTime, ACTION_COST},
+    AppState, PlayerClass, SelectedClass, BRAZIER_LIT_INDEX, MAP_HEIGHT, MAP_WIDTH, TORCH_LIT_INDEX,
 };
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::InGame), setup_game)
+        app.add_event::<TileChanged>()
+            .add_systems(OnEnter(AppState::InGame), setup_game)
+            .add_systems(OnExit(AppState::GameOver), despawn_level)
             .add_systems(
                 Update,
-                (
-                    player_movement,
-                    camera_follow_system,
-                    enemy_random_movement,
-                )
-                    .chain()
+                camera_follow_system
+                    .after(ActionSet)
                     .run_if(in_state(AppState::InGame)),
             );
     }
@@ -30,11 +44,115 @@ fn setup_game(
     selected_class: Res<SelectedClass>,
     asset_server: Res<AssetServer>,
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    (spells, monsters, encounters, combat): (Spells, Monsters, Encounters, Combat),
 ) {
-    let tile_texture = asset_server.load("tiles.png");
-    let tile_layout = TextureAtlasLayout::from_grid(UVec2::splat(32), 17, 26, None, None);
-    let tile_texture_atlas_layout = texture_atlas_layouts.add(tile_layout);
+    let start = spawn_level(
+        &mut commands,
+        &asset_server,
+        &mut texture_atlas_layouts,
+        1,
+        (spells.registry(), monsters.registry(), encounters.table()),
+    );
+
+    // Spawn player in center of first room
+    if let Some(class) = selected_class.0 {
+        let texture = asset_server.load("rogues.png");
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 7, 7, None, None);
+        let texture_atlas_layout = texture_atlas_layouts.add(layout);
+        let index = match class {
+            PlayerClass::Mage => 29,
+            PlayerClass::Warrior => 0,
+            PlayerClass::Ranger => 2,
+        };
+
+        let stats = Stats::for_class(class);
+        let weapons = combat.weapons(class);
+        let mut player = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..default()
+                },
+                texture: texture.clone(),
+                transform: Transform::from_translation(start.to_world(RenderLayer::Creature.z())),
+                ..default()
+            },
+            TextureAtlas {
+                layout: texture_atlas_layout,
+                index,
+            },
+            start,
+            RenderLayer::Creature,
+            Player,
+            Name::new("you"),
+            class,
+            (
+                Health(stats.max_hp),
+                Mana {
+                    current: stats.magic * MANA_PER_MAGIC,
+                    max: stats.magic * MANA_PER_MAGIC,
+                },
+                stats,
+                StatModifiers::default(),
+                Abilities::for_class(class),
+                Spellbook(spells.registry().starting_spells(class)),
+            ),
+            MeleeAttack(weapons.melee),
+            Inventory::default(),
+            Energy(ACTION_COST),
+            Viewshed {
+                range: 8,
+                ..default()
+            },
+            // Rangers carry a shuttered lantern so they can keep to the shadows
+            match class {
+                PlayerClass::Warrior => LightSource {
+                    radius: 5,
+                    intensity: 1.0,
+                },
+                PlayerClass::Mage => LightSource {
+                    radius: 4,
+                    intensity: 0.8,
+                },
+                PlayerClass::Ranger => LightSource {
+                    radius: 2,
+                    intensity: 0.4,
+                },
+            },
+            Digger {
+                power: match class {
+                    PlayerClass::Warrior => 2, // hand axe
+                    PlayerClass::Mage => 3,    // stone to mud
+                    PlayerClass::Ranger => 1,  // hatchet
+                },
+            },
+        ));
+        if let Some(ranged) = weapons.ranged {
+            player.insert(ranged);
+        }
+    } else {
+        panic!("No class selected!");
+    }
+
+    commands.insert_resource(Depth(1));
+    commands.insert_resource(GameTime::default());
+}
 
+/// Clears away the level the player died on before the next game.
+fn despawn_level(mut commands: Commands, level_query: Query<Entity, With<LevelEntity>>) {
+    for entity in &level_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Generates and spawns a fresh level, returning where the player arrives.
+pub fn spawn_level(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    depth: u32,
+    (spells, monsters, encounters): (&SpellRegistry, &MonsterRegistry, &EncounterTable),
+) -> Position {
     let mut rng = rand::thread_rng();
     let rooms: Vec<Room> = bsp_split(
         Rect {
@@ -47,86 +165,53 @@ fn setup_game(
         &mut rng,
     );
 
-    let mut floor_positions = HashSet::new();
+    let mut map = DungeonMap::new(MAP_WIDTH as i32, MAP_HEIGHT as i32);
 
-    // Spawn rooms
+    // Carve rooms
     for room in &rooms {
         for y in room.inner.y..room.inner.y + room.inner.height {
             for x in room.inner.x..room.inner.x + room.inner.width {
-                floor_positions.insert(Position { x, y });
-                commands.spawn((
-                    SpriteBundle {
-                        texture: tile_texture.clone(),
-                        transform: Transform::from_translation(Vec3::new(
-                            x as f32 * 32.0,
-                            y as f32 * 32.0,
-                            0.0,
-                        )),
-                        ..default()
-                    },
-                    TextureAtlas {
-                        layout: tile_texture_atlas_layout.clone(),
-                        index: FLOOR_TILE_INDEX,
-                    },
-                    Position { x, y },
-                    RoomId(room.id),
-                ));
+                map.set_tile(Position { x, y }, Tile::Floor);
+                map.set_room(Position { x, y }, room.id);
             }
         }
     }
 
-    // Spawn corridors
+    // Carve corridors
     for i in 1..rooms.len() {
         let (x1, y1) = rooms[i - 1].inner.center();
         let (x2, y2) = rooms[i].inner.center();
 
         if rng.gen_bool(0.5) {
             for x in x1.min(x2)..=x1.max(x2) {
-                floor_positions.insert(Position { x, y: y1 });
-                spawn_floor_tile(
-                    &mut commands,
-                    x,
-                    y1,
-                    tile_texture.clone(),
-                    tile_texture_atlas_layout.clone(),
-                );
+                map.set_tile(Position { x, y: y1 }, Tile::Floor);
             }
             for y in y1.min(y2)..=y1.max(y2) {
-                floor_positions.insert(Position { x: x2, y });
-                spawn_floor_tile(
-                    &mut commands,
-                    x2,
-                    y,
-                    tile_texture.clone(),
-                    tile_texture_atlas_layout.clone(),
-                );
+                map.set_tile(Position { x: x2, y }, Tile::Floor);
             }
         } else {
             for y in y1.min(y2)..=y1.max(y2) {
-                floor_positions.insert(Position { x: x1, y });
-                spawn_floor_tile(
-                    &mut commands,
-                    x1,
-                    y,
-                    tile_texture.clone(),
-                    tile_texture_atlas_layout.clone(),
-                );
+                map.set_tile(Position { x: x1, y }, Tile::Floor);
             }
             for x in x1.min(x2)..=x1.max(x2) {
-                floor_positions.insert(Position { x, y: y2 });
-                spawn_floor_tile(
-                    &mut commands,
-                    x,
-                    y2,
-                    tile_texture.clone(),
-                    tile_texture_atlas_layout.clone(),
-                );
+                map.set_tile(Position { x, y: y2 }, Tile::Floor);
             }
         }
     }
 
-    // Spawn walls around floors
+    hang_doors(&mut map, &rooms, &mut rng);
+    place_stairs(&mut map, &rooms);
+
+    // Wall in the floors, using the material of the adjoining room
+    let room_materials: Vec<WallMaterial> = rooms
+        .iter()
+        .map(|_| WallMaterial::ALL[rng.gen_range(0..WallMaterial::ALL.len())])
+        .collect();
+    let floor_positions: Vec<Position> = map.positions().filter(|&p| map.is_floor(p)).collect();
     for pos in &floor_positions {
+        let material = map
+            .room_at(*pos)
+            .map_or(WallMaterial::Stone, |room| room_materials[room]);
         for dy in -1..=1 {
             for dx in -1..=1 {
                 if dx == 0 && dy == 0 {
@@ -136,149 +221,148 @@ fn setup_game(
                     x: pos.x + dx,
                     y: pos.y + dy,
                 };
-                if !floor_positions.contains(&neighbor) {
-                    spawn_wall_tile(
-                        &mut commands,
-                        neighbor.x,
-                        neighbor.y,
-                        tile_texture.clone(),
-                        tile_texture_atlas_layout.clone(),
-                    );
+                if map.tile(neighbor) == Tile::Void {
+                    map.set_tile(neighbor, Tile::Wall(material));
                 }
             }
         }
     }
 
-    spawn_minimap_ui_tiles(&mut commands, &asset_server, &rooms);
-
-
-
-
-     // === Spawn Enemies ===
-    let enemy_texture = asset_server.load("monsters.png"); // reuse or use a new texture
-    let enemy_layout = TextureAtlasLayout::from_grid(UVec2::splat(32), 7, 7, None, None);
-    let enemy_atlas = texture_atlas_layouts.add(enemy_layout);
-
-    let mut rng = rand::thread_rng();
+    spawn_minimap_ui_tiles(commands, &map);
+
+    // Creatures never share a cell; the player arrives in the middle of the first room
+    let (x, y) = rooms[0].inner.center();
+    let start = Position { x, y };
+    let mut occupied = HashSet::from([start]);
+
+    // === Spawn Enemies ===
+    // Each group gathers around a spot of its own, well away from where the player arrives
+    let theme = LevelTheme::ALL[rng.gen_range(0..LevelTheme::ALL.len())];
+    for encounter in encounters.roll_level(monsters, depth, theme, &mut rng) {
+        let Some(anchor) = map
+            .positions()
+            .filter(|p| {
+                map.is_walkable(*p)
+                    && !occupied.contains(p)
+                    && map.room_at(*p) != Some(rooms[0].id)
+                    && p.x.abs_diff(start.x) + p.y.abs_diff(start.y) >= MIN_ENCOUNTER_DISTANCE
+            })
+            .choose(&mut rng)
+        else {
+            break;
+        };
+        let cells = gather_cells(&map, anchor, &occupied);
+        let spawned = spawn_encounter(
+            commands,
+            asset_server,
+            texture_atlas_layouts,
+            (encounter, spells),
+            cells,
+            &mut rng,
+        );
+        occupied.extend(spawned.into_iter().map(|(_, pos)| pos));
+    }
+    commands.insert_resource(theme);
+
+    // === Spawn Spell Books and Scrolls ===
+    if !spells.spells.is_empty() && rooms.len() > 1 && rng.gen_bool(0.4) {
+        let room = &rooms[rng.gen_range(1..rooms.len())];
+        let pos = Position {
+            x: rng.gen_range(room.inner.x..room.inner.x + room.inner.width),
+            y: rng.gen_range(room.inner.y..room.inner.y + room.inner.height),
+        };
+        let spell = SpellId(rng.gen_range(0..spells.spells.len()));
+        let kind = if rng.gen_bool(0.5) {
+            ItemKind::Book {
+                spell,
+                icon: spells.spells[spell.0].icon,
+            }
+        } else {
+            ItemKind::Scroll(Scroll::Spell(spell))
+        };
+        spawn_item(commands, asset_server, texture_atlas_layouts, kind, pos);
+    }
 
+    // === Spawn Potions and Scrolls ===
     for room in rooms.iter().skip(1) {
-        if rng.gen_bool(0.6) { // ~60% chance to have enemy in this room
-            let (x, y) = room.inner.center();
-            commands.spawn((
-                SpriteBundle {
-                    texture: enemy_texture.clone(),
-                    transform: Transform::from_translation(Vec3::new(x as f32 * 32.0, y as f32 * 32.0, 1.0)),
-                    ..default()
-                },
-                TextureAtlas {
-                    layout: enemy_atlas.clone(),
-                    index: 4, // some enemy sprite
-                },
-                Position { x, y },
-                Enemy,
-                Health(10),
-            ));
+        if !rng.gen_bool(0.25) {
+            continue;
         }
+        let pos = Position {
+            x: rng.gen_range(room.inner.x..room.inner.x + room.inner.width),
+            y: rng.gen_range(room.inner.y..room.inner.y + room.inner.height),
+        };
+        let kind = ItemKind::FOUND[rng.gen_range(0..ItemKind::FOUND.len())];
+        spawn_item(commands, asset_server, texture_atlas_layouts, kind, pos);
     }
 
+    // === Spawn Light Fixtures ===
+    let fixture_texture = asset_server.load("animated-tiles.png");
+    let fixture_layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 11, 12, None, None);
+    let fixture_atlas = texture_atlas_layouts.add(fixture_layout);
 
-    // Spawn player in center of first room
-    if let Some(class) = selected_class.0 {
-        let texture = asset_server.load("rogues.png");
-        let layout = TextureAtlasLayout::from_grid(UVec2::splat(32), 7, 7, None, None);
-        let texture_atlas_layout = texture_atlas_layouts.add(layout);
-        let index = match class {
-            PlayerClass::Mage => 29,
-            PlayerClass::Warrior => 0,
-            PlayerClass::Ranger => 2,
+    for room in &rooms {
+        // Torches hang on the wall above the room, braziers stand in a corner
+        let (fixture, pos, light) = match rng.gen_range(0..3) {
+            0 => (
+                TORCH_LIT_INDEX,
+                Position {
+                    x: room.inner.center().0,
+                    y: room.inner.y + room.inner.height,
+                },
+                LightSource {
+                    radius: 5,
+                    intensity: 0.8,
+                },
+            ),
+            1 => (
+                BRAZIER_LIT_INDEX,
+                Position {
+                    x: room.inner.x,
+                    y: room.inner.y,
+                },
+                LightSource {
+                    radius: 6,
+                    intensity: 1.0,
+                },
+            ),
+            _ => continue,
         };
-
-        let (x, y) = rooms[0].inner.center();
-        commands.spawn((
+        if !map.in_bounds(pos) {
+            continue;
+        }
+        let mut entity = commands.spawn((
             SpriteBundle {
-                texture: texture.clone(),
-                transform: Transform::from_translation(Vec3::new(
-                    x as f32 * 32.0,
-                    y as f32 * 32.0,
-                    1.0,
-                )),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..default()
+                },
+                texture: fixture_texture.clone(),
+                transform: Transform::from_translation(pos.to_world(RenderLayer::Furniture.z())),
                 ..default()
             },
             TextureAtlas {
-                layout: texture_atlas_layout,
-                index,
+                layout: fixture_atlas.clone(),
+                index: fixture,
             },
-            Position { x, y },
-            Player,
+            AnimatedTile {
+                first: fixture,
+                frames: 6,
+                timer: Timer::from_seconds(0.15, TimerMode::Repeating),
+            },
+            pos,
+            RenderLayer::Furniture,
+            light,
+            LevelEntity,
         ));
-    } else {
-        panic!("No class selected!");
-    }
-}
-
-fn player_movement(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut param_set: ParamSet<(
-        Query<(&mut Transform, &mut Position), (Without<Wall>, With<Player>)>,
-        Query<&mut Transform, (With<MinimapTile>, With<Player>)>,
-    )>,
-    wall_query: Query<&Position, With<Wall>>,
-) {
-    let mut delta = (0, 0);
-    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
-        delta.1 += 1;
-    }
-    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
-        delta.1 -= 1;
-    }
-    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
-        delta.0 -= 1;
-    }
-    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
-        delta.0 += 1;
-    }
-
-    if delta == (0, 0) {
-        return;
-    }
-
-    let mut player_query = param_set.p0();
-
-    let (player_transform, player_pos) = match player_query.get_single() {
-        Ok((t, p)) => (t, p),
-        Err(_) => return,
-    };
-
-    let new_pos = Position {
-        x: player_pos.x + delta.0,
-        y: player_pos.y + delta.1,
-    };
-
-    let blocked = wall_query.iter().any(|&pos| pos == new_pos);
-    if blocked {
-        return;
-    }
-
-    let minimap_tile_size = 4.0;
-    let minimap_offset = Vec2::new(
-        -(MAP_WIDTH as f32 * minimap_tile_size) / 2.0,
-        -(MAP_HEIGHT as f32 * minimap_tile_size) / 2.0,
-    );
-
-    if let Ok((mut transform, mut pos)) = player_query.get_single_mut() {
-        pos.x = new_pos.x;
-        pos.y = new_pos.y;
-        transform.translation = Vec3::new(new_pos.x as f32 * 32.0, new_pos.y as f32 * 32.0, 1.0);
+        // A brazier stands tall enough to hide whatever is behind it
+        if fixture == BRAZIER_LIT_INDEX {
+            entity.insert(BlocksSight);
+        }
     }
 
-    let mut minimap_query = param_set.p1();
-    if let Ok(mut mini_transform) = minimap_query.get_single_mut() {
-        mini_transform.translation = Vec3::new(
-            new_pos.x as f32 * minimap_tile_size + minimap_offset.x,
-            new_pos.y as f32 * minimap_tile_size + minimap_offset.y,
-            11.0,
-        );
-    }
+    commands.insert_resource(map);
+    start
 }
 
 fn camera_follow_system(
@@ -292,49 +376,5 @@ fn camera_follow_system(
         return;
     };
 
-    camera_transform.translation.x = player_pos.x as f32 * 32.0;
-    camera_transform.translation.y = player_pos.y as f32 * 32.0;
-}
-
-fn enemy_random_movement(
-    mut param_set: ParamSet<(
-        Query<(&mut Transform, &mut Position), With<Enemy>>,
-        Query<&Position, With<Wall>>,
-    )>,
-    time: Res<Time>,
-    mut timer: Local<Timer>,
-) {
-    if timer.duration().is_zero() {
-        *timer = Timer::from_seconds(1.0, TimerMode::Repeating);
-    }
-
-    if timer.tick(time.delta()).just_finished() {
-        let mut rng = rand::thread_rng();
-
-        // Step 1: Get a vector of all wall positions
-        let wall_positions: Vec<Position> = param_set.p1().iter().copied().collect();
-
-        // Step 2: Now it's safe to use the enemy query mutably
-        for (mut transform, mut pos) in param_set.p0().iter_mut() {
-            let delta = match rng.gen_range(0..4) {
-                0 => (0, 1),
-                1 => (0, -1),
-                2 => (-1, 0),
-                _ => (1, 0),
-            };
-
-            let new_pos = Position {
-                x: pos.x + delta.0,
-                y: pos.y + delta.1,
-            };
-
-            if wall_positions.contains(&new_pos) {
-                continue;
-            }
-
-            pos.x = new_pos.x;
-            pos.y = new_pos.y;
-            transform.translation = Vec3::new(new_pos.x as f32 * 32.0, new_pos.y as f32 * 32.0, 1.0);
-        }
-    }
+    camera_transform.translation = player_pos.to_world(camera_transform.translation.z);
 }
@@ -0,0 +1,82 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::components::{Position, RenderLayer};
+
+/// World-space size of one map cell.
+pub const TILE_SIZE: f32 = 32.0;
+
+/// Pixel size of one cell in the sprite sheets under `assets/`.
+pub const ATLAS_CELL_SIZE: u32 = 32;
+
+/// Plugin that keeps every entity's `Transform` in step with its grid `Position` and `RenderLayer`.
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            sync_position_transforms.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+impl Position {
+    /// Center of this cell in world space at depth `z`.
+    pub fn to_world(self, z: f32) -> Vec3 {
+        Vec3::new(self.x as f32 * TILE_SIZE, self.y as f32 * TILE_SIZE, z)
+    }
+
+    pub fn offset(self, delta: IVec2) -> Position {
+        Position {
+            x: self.x + delta.x,
+            y: self.y + delta.y,
+        }
+    }
+
+    /// Whether `other` is one of the eight cells around this one.
+    pub fn is_adjacent(self, other: Position) -> bool {
+        self != other && (self.x - other.x).abs() <= 1 && (self.y - other.y).abs() <= 1
+    }
+}
+
+/// The cell containing a world-space point.
+pub fn world_to_grid(world: Vec2) -> Position {
+    Position {
+        x: (world.x / TILE_SIZE).round() as i32,
+        y: (world.y / TILE_SIZE).round() as i32,
+    }
+}
+
+/// Cells on the Bresenham line from `from` to `to`, both ends included.
+pub fn line_between(from: Position, to: Position) -> Vec<Position> {
+    let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let (sx, sy) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut err = dx + dy;
+    let mut current = from;
+    let mut cells = vec![current];
+
+    while current != to {
+        let doubled = 2 * err;
+        if doubled >= dy {
+            err += dy;
+            current.x += sx;
+        }
+        if doubled <= dx {
+            err += dx;
+            current.y += sy;
+        }
+        cells.push(current);
+    }
+    cells
+}
+
+type Moved = Or<(Changed<Position>, Changed<RenderLayer>)>;
+
+fn sync_position_transforms(
+    mut query: Query<(&Position, Option<&RenderLayer>, &mut Transform), Moved>,
+) {
+    for (pos, layer, mut transform) in &mut query {
+        let z = layer.map_or(transform.translation.z, |layer| layer.z());
+        transform.translation = pos.to_world(z);
+    }
+}
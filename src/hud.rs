@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+
+use crate::{
+    abilities::Abilities,
+    components::{Health, Mana, Player},
+    items::{Inventory, ITEM_KEYS},
+    spells::{Spellbook, Spells, SPELL_KEYS},
+    stats::DerivedStats,
+    AppState,
+};
+
+/// Lines of the message log shown on screen at once.
+const VISIBLE_MESSAGES: usize = 6;
+
+/// Plugin that draws the in-game HUD: the message log and the player's vitals.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MessageLog>()
+            .add_systems(OnEnter(AppState::InGame), setup_hud)
+            .add_systems(OnExit(AppState::GameOver), cleanup_hud)
+            .add_systems(
+                Update,
+                (
+                    update_message_log,
+                    update_vitals,
+                    update_ability_bar,
+                    update_spell_bar,
+                    update_item_bar,
+                )
+                    // Keep running through game over so the last words make it onto the log
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::GameOver))),
+            );
+    }
+}
+
+/// Everything that has happened this game, newest last.
+#[derive(Resource, Default)]
+pub struct MessageLog {
+    lines: Vec<String>,
+}
+
+impl MessageLog {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.lines.push(message.into());
+    }
+}
+
+/// Top-level HUD nodes, torn down when the game ends.
+#[derive(Component)]
+struct HudRoot;
+
+#[derive(Component)]
+struct MessageLogText;
+
+#[derive(Component)]
+struct VitalsText;
+
+#[derive(Component)]
+struct AbilityBarText;
+
+#[derive(Component)]
+struct SpellBarText;
+
+#[derive(Component)]
+struct ItemBarText;
+
+fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.insert_resource(MessageLog::default());
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 18.,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            ..default()
+        }),
+        MessageLogText,
+        HudRoot,
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 22.,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..default()
+        }),
+        VitalsText,
+        HudRoot,
+    ));
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(HudRoot)
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.,
+                        color: Color::srgb(0.6, 0.8, 1.0),
+                    },
+                ),
+                SpellBarText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.,
+                        color: Color::WHITE,
+                    },
+                ),
+                AbilityBarText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: 20.,
+                        color: Color::srgb(1.0, 0.85, 0.6),
+                    },
+                ),
+                ItemBarText,
+            ));
+        });
+}
+
+fn cleanup_hud(mut commands: Commands, hud_query: Query<Entity, With<HudRoot>>) {
+    for entity in &hud_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn update_message_log(log: Res<MessageLog>, mut text_query: Query<&mut Text, With<MessageLogText>>) {
+    if !log.is_changed() {
+        return;
+    }
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    let start = log.lines.len().saturating_sub(VISIBLE_MESSAGES);
+    text.sections[0].value = log.lines[start..].join("\n");
+}
+
+fn update_vitals(
+    player_query: Query<(&Health, Option<&Mana>, &DerivedStats), With<Player>>,
+    mut text_query: Query<&mut Text, With<VitalsText>>,
+) {
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    let vitals = match player_query.get_single() {
+        Ok((health, Some(mana), DerivedStats(stats))) if mana.max > 0 => {
+            format!("HP: {}/{}   MP: {}/{}", health.0, stats.max_hp, mana.current, mana.max)
+        }
+        Ok((health, _, DerivedStats(stats))) => format!("HP: {}/{}", health.0, stats.max_hp),
+        Err(_) => "You are dead".to_string(),
+    };
+    if text.sections[0].value != vitals {
+        text.sections[0].value = vitals;
+    }
+}
+
+/// One entry per ability slot: its hotkey, name and remaining cooldown.
+fn update_ability_bar(
+    player_query: Query<&Abilities, (With<Player>, Changed<Abilities>)>,
+    mut text_query: Query<&mut Text, With<AbilityBarText>>,
+) {
+    let Ok(abilities) = player_query.get_single() else {
+        return;
+    };
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    let entries: Vec<String> = abilities
+        .0
+        .iter()
+        .enumerate()
+        .map(|(i, slot)| match slot.cooldown {
+            0 => format!("[{}] {}", i + 1, slot.ability.name()),
+            turns => format!("[{}] {} ({turns})", i + 1, slot.ability.name()),
+        })
+        .collect();
+    text.sections[0].value = entries.join("   ");
+}
+
+/// Carried potions and scrolls with their hotkeys.
+fn update_item_bar(
+    player_query: Query<&Inventory, (With<Player>, Changed<Inventory>)>,
+    mut text_query: Query<&mut Text, With<ItemBarText>>,
+) {
+    let Ok(inventory) = player_query.get_single() else {
+        return;
+    };
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    let entries: Vec<String> = ITEM_KEYS
+        .iter()
+        .zip(&inventory.items)
+        .map(|(key, item)| {
+            let key = format!("{key:?}");
+            format!("[{}] {}", key.trim_start_matches("Digit"), item.name())
+        })
+        .collect();
+    text.sections[0].value = entries.join("   ");
+}
+
+/// Known spells with their hotkeys and mana costs.
+fn update_spell_bar(
+    spells: Spells,
+    player_query: Query<&Spellbook, With<Player>>,
+    mut text_query: Query<&mut Text, With<SpellBarText>>,
+) {
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    let entries: Vec<String> = player_query
+        .get_single()
+        .into_iter()
+        .flat_map(|spellbook| SPELL_KEYS.iter().zip(&spellbook.0))
+        .filter_map(|(key, id)| {
+            let spell = spells.get(*id)?;
+            let key = format!("{key:?}");
+            Some(format!("[{}] {} ({} mp)", key.trim_start_matches("Key"), spell.name, spell.cost))
+        })
+        .collect();
+    let bar = entries.join("   ");
+    if text.sections[0].value != bar {
+        text.sections[0].value = bar;
+    }
+}
@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+use rand::seq::IteratorRandom;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    components::{Energy, Health, LevelEntity, Mana, Player, PlayersTurn, Position, RenderLayer},
+    dice::Dice,
+    grid::{ATLAS_CELL_SIZE, TILE_SIZE},
+    hud::MessageLog,
+    map::DungeonMap,
+    spells::{SpellId, Spellbook, Spells},
+    stats::DerivedStats,
+    targeting::Targeting,
+    AppState, BLUE_POTION_INDEX, COINS_INDEX, RED_POTION_INDEX, SCROLL_INDEX,
+};
+
+/// Hotkeys for the potions and scrolls the player carries, in the order they were picked up.
+pub const ITEM_KEYS: [KeyCode; 5] = [
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+const HEALING: Dice = Dice::new(2, 4, 4);
+const RESTORE_MANA: Dice = Dice::new(2, 4, 2);
+
+/// Plugin for items lying on the floor, picking them up and using them.
+pub struct ItemsPlugin;
+
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                item_input
+                    .run_if(not(resource_exists::<Targeting>))
+                    .before(ActionSet),
+                (resolve_pick_up, resolve_use_item).in_set(ActionSet),
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Gold(u32),
+    /// A book or tome that teaches a spell as soon as it is picked up; `icon` is its sprite in `items.png`.
+    Book { spell: SpellId, icon: usize },
+    Potion(Potion),
+    Scroll(Scroll),
+}
+
+impl ItemKind {
+    /// Potions and scrolls that turn up lying around a level.
+    pub const FOUND: [ItemKind; 3] = [
+        ItemKind::Potion(Potion::Healing),
+        ItemKind::Potion(Potion::Mana),
+        ItemKind::Scroll(Scroll::Teleportation),
+    ];
+
+    /// Potions and scrolls go in the pack to be used later; everything else takes effect when picked up.
+    pub fn is_carried(self) -> bool {
+        matches!(self, ItemKind::Potion(_) | ItemKind::Scroll(_))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemKind::Gold(_) => "gold",
+            ItemKind::Book { .. } => "a book",
+            ItemKind::Potion(Potion::Healing) => "a potion of healing",
+            ItemKind::Potion(Potion::Mana) => "a potion of mana",
+            ItemKind::Scroll(Scroll::Teleportation) => "a scroll of teleportation",
+            ItemKind::Scroll(Scroll::Spell(_)) => "a spell scroll",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Potion {
+    Healing,
+    Mana,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scroll {
+    /// Whisks the reader away to somewhere else on the level.
+    Teleportation,
+    /// Teaches its spell to the reader.
+    Spell(SpellId),
+}
+
+/// Something lying on the floor that can be picked up.
+#[derive(Component)]
+pub struct Item(pub ItemKind);
+
+/// What an actor carries.
+#[derive(Component, Default)]
+pub struct Inventory {
+    pub gold: u32,
+    /// Potions and scrolls, bound to the item keys in order.
+    pub items: Vec<ItemKind>,
+}
+
+/// Drops an item on the current level.
+pub fn spawn_item(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    kind: ItemKind,
+    pos: Position,
+) {
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 11, 26, None, None);
+    let index = match kind {
+        ItemKind::Gold(_) => COINS_INDEX,
+        ItemKind::Book { icon, .. } => icon,
+        ItemKind::Potion(Potion::Healing) => RED_POTION_INDEX,
+        ItemKind::Potion(Potion::Mana) => BLUE_POTION_INDEX,
+        ItemKind::Scroll(_) => SCROLL_INDEX,
+    };
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..default()
+            },
+            texture: asset_server.load("items.png"),
+            transform: Transform::from_translation(pos.to_world(RenderLayer::Item.z())),
+            ..default()
+        },
+        TextureAtlas {
+            layout: texture_atlas_layouts.add(layout),
+            index,
+        },
+        pos,
+        RenderLayer::Item,
+        Item(kind),
+        LevelEntity,
+    ));
+}
+
+/// Adds `spell` to the spellbook, unless there is none or the spell is already in it.
+fn learn(spellbook: Option<&mut Spellbook>, spell: SpellId) -> bool {
+    match spellbook {
+        Some(spellbook) if !spellbook.0.contains(&spell) => {
+            spellbook.0.push(spell);
+            true
+        }
+        _ => false,
+    }
+}
+
+type PickerData<'a> = (&'a Position, &'a mut Inventory, Option<&'a mut Spellbook>, &'a mut Energy, Has<Player>);
+
+fn resolve_pick_up(
+    mut commands: Commands,
+    mut action_events: EventReader<ActionEvent>,
+    spells: Spells,
+    mut actor_query: Query<PickerData>,
+    item_query: Query<(Entity, &Position, &Item)>,
+    mut log: ResMut<MessageLog>,
+) {
+    for event in action_events.read() {
+        if event.action != GameAction::PickUp {
+            continue;
+        }
+        let Ok((pos, mut inventory, mut spellbook, mut energy, is_player)) = actor_query.get_mut(event.actor) else {
+            continue;
+        };
+
+        let mut succeeded = false;
+        let mut left_behind = false;
+        for (item, item_pos, Item(kind)) in &item_query {
+            if item_pos != pos {
+                continue;
+            }
+            if kind.is_carried() && inventory.items.len() >= ITEM_KEYS.len() {
+                if is_player {
+                    log.push(format!("You have no room for {}.", kind.name()));
+                }
+                left_behind = true;
+                continue;
+            }
+            match *kind {
+                ItemKind::Gold(amount) => {
+                    inventory.gold += amount;
+                    if is_player {
+                        log.push(format!("You pick up {amount} gold."));
+                    }
+                }
+                ItemKind::Book { spell, .. } => {
+                    let name = spells.get(spell).map_or("a spell", |def| def.name.as_str());
+                    let learned = learn(spellbook.as_deref_mut(), spell);
+                    if is_player {
+                        log.push(if learned {
+                            format!("You study the book and learn {name}.")
+                        } else {
+                            format!("You already know {name}; the book crumbles to dust.")
+                        });
+                    }
+                }
+                ItemKind::Potion(_) | ItemKind::Scroll(_) => {
+                    inventory.items.push(*kind);
+                    if is_player {
+                        log.push(format!("You pick up {}.", kind.name()));
+                    }
+                }
+            }
+            commands.entity(item).despawn_recursive();
+            succeeded = true;
+        }
+        if !succeeded && !left_behind && is_player {
+            log.push("There is nothing here.");
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
+
+fn item_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player_query: Query<(Entity, &Inventory), PlayersTurn>,
+    mut action_events: EventWriter<ActionEvent>,
+) {
+    let Ok((player, inventory)) = player_query.get_single() else {
+        return;
+    };
+
+    let pressed = ITEM_KEYS.iter().take(inventory.items.len()).position(|key| keyboard_input.just_pressed(*key));
+    if let Some(slot) = pressed {
+        action_events.send(ActionEvent {
+            actor: player,
+            action: GameAction::UseItem(slot),
+        });
+    }
+}
+
+type UserData<'a> = (
+    &'a mut Inventory,
+    &'a DerivedStats,
+    &'a mut Health,
+    Option<&'a mut Mana>,
+    Option<&'a mut Spellbook>,
+    &'a mut Energy,
+    Has<Player>,
+);
+
+fn resolve_use_item(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    spells: Spells,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<UserData>,
+    creature_query: Query<&Position, With<Health>>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for event in action_events.read() {
+        let GameAction::UseItem(slot) = event.action else {
+            continue;
+        };
+        let Ok((mut inventory, DerivedStats(stats), mut health, mana, spellbook, mut energy, is_player)) =
+            actor_query.get_mut(event.actor)
+        else {
+            continue;
+        };
+        if slot >= inventory.items.len() {
+            if is_player {
+                log.push("You have nothing to use there.");
+            }
+            settle(&mut commands, event.actor, &mut energy, is_player, false);
+            continue;
+        }
+
+        let item = inventory.items.remove(slot);
+        match item {
+            ItemKind::Potion(Potion::Healing) => {
+                let healed = HEALING.roll(&mut rng).total().min(stats.max_hp - health.0);
+                health.0 += healed;
+                if is_player {
+                    log.push(format!("You drink {} and recover {healed} HP.", item.name()));
+                }
+            }
+            ItemKind::Potion(Potion::Mana) => {
+                let restored = mana.map_or(0, |mut mana| {
+                    let restored = RESTORE_MANA.roll(&mut rng).total().min(mana.max - mana.current);
+                    mana.current += restored;
+                    restored
+                });
+                if is_player {
+                    log.push(format!("You drink {} and recover {restored} MP.", item.name()));
+                }
+            }
+            ItemKind::Scroll(Scroll::Teleportation) => {
+                let destination = map
+                    .positions()
+                    .filter(|p| map.is_walkable(*p) && !creature_query.iter().any(|c| c == p))
+                    .choose(&mut rng);
+                if let Some(destination) = destination {
+                    commands.entity(event.actor).insert(destination);
+                }
+                if is_player {
+                    log.push(format!("You read {} and the world lurches around you.", item.name()));
+                }
+            }
+            ItemKind::Scroll(Scroll::Spell(spell)) => {
+                let name = spells.get(spell).map_or("a spell", |def| def.name.as_str());
+                let learned = learn(spellbook.map(Mut::into_inner), spell);
+                if is_player {
+                    log.push(if learned {
+                        format!("You read the scroll and learn {name}.")
+                    } else {
+                        format!("You already know {name}; the scroll crumbles to dust.")
+                    });
+                }
+            }
+            ItemKind::Gold(_) | ItemKind::Book { .. } => {}
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, true);
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{LightSource, Position},
+    fov::{compute_fov, PlayerVisibilitySet},
+    map::{DungeonMap, TileChanged, TileVisibility},
+    tilemap::REMEMBERED_TINT,
+    AppState,
+};
+
+/// Light below which the player cannot make out a tile beyond arm's reach.
+pub const SEE_LIGHT_THRESHOLD: f32 = 0.05;
+
+/// Light the player must stand in before monsters can notice them from afar.
+pub const NOTICE_LIGHT_THRESHOLD: f32 = 0.5;
+
+/// Light level every tile receives without any source nearby.
+pub const AMBIENT_LIGHT: f32 = 0.0;
+
+/// Plugin that computes the light map from every `LightSource` and tints sprites by it.
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightMap>()
+            .add_event::<TileLightChanged>()
+            .add_systems(
+                Update,
+                (
+                    update_light_map.before(PlayerVisibilitySet),
+                    (animate_tiles, tint_sprites_by_light),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Sent when the light falling on a tile changes.
+#[derive(Event)]
+pub struct TileLightChanged {
+    pub pos: Position,
+}
+
+/// Light level in `0.0..=1.0` for every tile of the current level.
+#[derive(Resource, Default)]
+pub struct LightMap {
+    width: i32,
+    height: i32,
+    levels: Vec<f32>,
+}
+
+impl LightMap {
+    pub fn light_at(&self, pos: Position) -> f32 {
+        if pos.x < 0 || pos.y < 0 || pos.x >= self.width || pos.y >= self.height {
+            return AMBIENT_LIGHT;
+        }
+        self.levels[(pos.y * self.width + pos.x) as usize]
+    }
+}
+
+/// Colour a sprite shows in full light; without one it is drawn white.
+#[derive(Component, Clone, Copy)]
+pub struct BaseColor(pub Color);
+
+/// Cycles through a row of frames in `animated-tiles.png`.
+#[derive(Component)]
+pub struct AnimatedTile {
+    pub first: usize,
+    pub frames: usize,
+    pub timer: Timer,
+}
+
+fn update_light_map(
+    map: Res<DungeonMap>,
+    mut light_map: ResMut<LightMap>,
+    mut changed_events: EventReader<TileChanged>,
+    light_query: Query<(Ref<Position>, Ref<LightSource>)>,
+    mut removed_lights: RemovedComponents<LightSource>,
+    mut light_events: EventWriter<TileLightChanged>,
+) {
+    let map_changed = changed_events.read().count() > 0;
+    let lights_removed = removed_lights.read().count() > 0;
+    let lights_changed = light_query
+        .iter()
+        .any(|(pos, light)| pos.is_changed() || light.is_changed());
+    let resized = light_map.width != map.width || light_map.height != map.height;
+    if !map_changed && !lights_removed && !lights_changed && !resized {
+        return;
+    }
+
+    let mut levels = vec![AMBIENT_LIGHT; (map.width * map.height) as usize];
+    for (pos, light) in &light_query {
+        // Lights mounted in a wall shine out into the open side
+        let origin = if map.blocks_sight(*pos) {
+            let Some(open) = [(0, -1), (0, 1), (-1, 0), (1, 0)]
+                .into_iter()
+                .map(|(dx, dy)| Position { x: pos.x + dx, y: pos.y + dy })
+                .find(|&p| map.is_walkable(p))
+            else {
+                continue;
+            };
+            open
+        } else {
+            *pos
+        };
+
+        for lit in compute_fov(origin, light.radius, |p| map.blocks_sight(p)) {
+            if !map.in_bounds(lit) {
+                continue;
+            }
+            let distance = (((lit.x - origin.x).pow(2) + (lit.y - origin.y).pow(2)) as f32).sqrt();
+            let falloff = 1.0 - distance / (light.radius + 1) as f32;
+            let level = &mut levels[(lit.y * map.width + lit.x) as usize];
+            *level = (*level + light.intensity * falloff).min(1.0);
+        }
+    }
+
+    for pos in map.positions() {
+        let level = levels[(pos.y * map.width + pos.x) as usize];
+        if (level - light_map.light_at(pos)).abs() > f32::EPSILON {
+            light_events.send(TileLightChanged { pos });
+        }
+    }
+
+    *light_map = LightMap {
+        width: map.width,
+        height: map.height,
+        levels,
+    };
+}
+
+fn animate_tiles(time: Res<Time>, mut query: Query<(&mut AnimatedTile, &mut TextureAtlas)>) {
+    for (mut animation, mut atlas) in &mut query {
+        if animation.timer.tick(time.delta()).just_finished() {
+            let frame = (atlas.index - animation.first + 1) % animation.frames;
+            atlas.index = animation.first + frame;
+        }
+    }
+}
+
+/// Sprites in view are shaded by the light on their cell; remembered ones get the same tint as remembered tiles.
+fn tint_sprites_by_light(
+    map: Res<DungeonMap>,
+    light_map: Res<LightMap>,
+    mut query: Query<(&Position, &mut Sprite, Option<&BaseColor>)>,
+) {
+    for (pos, mut sprite, base) in &mut query {
+        let [r, g, b, _] = match map.visibility(*pos) {
+            TileVisibility::Visible => [0.3 + 0.7 * light_map.light_at(*pos); 4],
+            _ => REMEMBERED_TINT,
+        };
+        let base = base.map_or(Srgba::WHITE, |base| base.0.to_srgba());
+        sprite.color = Color::srgba(base.red * r, base.green * g, base.blue * b, base.alpha);
+    }
+}
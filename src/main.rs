@@ -1,33 +1,92 @@
 use bevy::{color::palettes::css::BLACK, prelude::*};
 
+use crate::abilities::AbilitiesPlugin;
+use crate::actions::ActionsPlugin;
+use crate::ai::AiPlugin;
+use crate::combat::CombatPlugin;
 use crate::components::*;
+use crate::data::DataPlugin;
+use crate::dig::DigPlugin;
+use crate::doors::DoorsPlugin;
+use crate::encounters::EncountersPlugin;
+use crate::fov::FovPlugin;
 use crate::game::GamePlugin;
+use crate::grid::GridPlugin;
+use crate::hud::HudPlugin;
+use crate::items::ItemsPlugin;
+use crate::lighting::LightingPlugin;
 use crate::menu::MenuPlugin;
 use crate::minimap::MinimapPlugin;
+use crate::monsters::MonstersPlugin;
+use crate::pathfinding::PathfindingPlugin;
+use crate::projectile::ProjectilePlugin;
+use crate::spells::SpellsPlugin;
+use crate::stairs::StairsPlugin;
+use crate::stats::StatsPlugin;
+use crate::targeting::TargetingPlugin;
+use crate::tilemap::TilemapPlugin;
+use crate::turn::TurnPlugin;
 
+mod abilities;
+mod actions;
+mod ai;
+mod combat;
 mod components;
+mod data;
+mod dice;
+mod dig;
+mod doors;
+mod encounters;
+mod fov;
 mod game;
+mod grid;
+mod hud;
+mod items;
+mod lighting;
 mod map;
 mod minimap;
 mod menu;
+mod monsters;
+mod pathfinding;
+mod projectile;
+mod spells;
+mod stairs;
+mod stats;
+mod targeting;
+mod tilemap;
+mod turn;
 
 pub const MINIMAP_LAYER: usize = 1;
 pub const MAP_WIDTH: usize = 24;
 pub const MAP_HEIGHT: usize = 24;
 
 pub const FLOOR_TILE_INDEX: usize = 119;
-pub const WALL_VERTICAL_INDEX: usize = 17; // e.g. │ sprite
-pub const WALL_HORIZONTAL_INDEX: usize = 18; // e.g. ─ sprite
+pub const DOOR_SHUT_INDEX: usize = 274;
+pub const DOOR_OPEN_INDEX: usize = 275;
+pub const STAIRS_DOWN_INDEX: usize = 279;
+pub const SPIKES_DOWN_INDEX: usize = 287;
+pub const SPIKES_UP_INDEX: usize = 288;
+pub const CORPSE_INDEX: usize = 357;
+pub const ARROW_INDEX: usize = 253; // items.png
+pub const SMALL_FIRE_INDEX: usize = 99; // animated-tiles.png
+pub const COINS_INDEX: usize = 265; // items.png, small stack of coins
+pub const RED_POTION_INDEX: usize = 210; // items.png
+pub const BLUE_POTION_INDEX: usize = 223; // items.png
+pub const SCROLL_INDEX: usize = 231; // items.png
+pub const BRAZIER_LIT_INDEX: usize = 11; // animated-tiles.png, 6 frames
+pub const TORCH_LIT_INDEX: usize = 55; // animated-tiles.png, 6 frames
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum AppState {
     #[default]
     Menu,
+    /// Waiting for the data files the game needs before it can start.
+    Loading,
     InGame,
+    /// The player has died; the last level stays on screen until they go back to the menu.
+    GameOver,
 }
 
-
-
 #[derive(Resource)]
 pub struct SelectedClass(pub Option<PlayerClass>);
 
@@ -38,6 +97,30 @@ fn main() {
             MenuPlugin,
             GamePlugin,
             MinimapPlugin,
+            DigPlugin,
+            TilemapPlugin,
+            GridPlugin,
+            FovPlugin,
+            LightingPlugin,
+            TurnPlugin,
+            ActionsPlugin,
+            DoorsPlugin,
+            StairsPlugin,
+        ))
+        .add_plugins((
+            CombatPlugin,
+            ItemsPlugin,
+            HudPlugin,
+            StatsPlugin,
+            AbilitiesPlugin,
+            TargetingPlugin,
+            ProjectilePlugin,
+            SpellsPlugin,
+            AiPlugin,
+            PathfindingPlugin,
+            MonstersPlugin,
+            EncountersPlugin,
+            DataPlugin,
         ))
         .insert_resource(ClearColor(BLACK.into()))
         .insert_resource(SelectedClass(None))
@@ -51,54 +134,3 @@ fn setup(mut commands: Commands) {
         CameraFollow,
     ));
 }
-
-fn spawn_floor_tile(
-    commands: &mut Commands,
-    x: i32,
-    y: i32,
-    texture: Handle<Image>,
-    layout: Handle<TextureAtlasLayout>,
-) {
-    commands.spawn((
-        SpriteBundle {
-            texture,
-            transform: Transform::from_translation(Vec3::new(
-                x as f32 * 32.0,
-                y as f32 * 32.0,
-                0.0,
-            )),
-            ..default()
-        },
-        TextureAtlas {
-            layout,
-            index: FLOOR_TILE_INDEX,
-        },
-        Position { x, y },
-    ));
-}
-
-fn spawn_wall_tile(
-    commands: &mut Commands,
-    x: i32,
-    y: i32,
-    texture: Handle<Image>,
-    layout: Handle<TextureAtlasLayout>,
-) {
-    commands.spawn((
-        SpriteBundle {
-            texture,
-            transform: Transform::from_translation(Vec3::new(
-                x as f32 * 32.0,
-                y as f32 * 32.0,
-                0.0,
-            )),
-            ..default()
-        },
-        TextureAtlas {
-            layout,
-            index: WALL_HORIZONTAL_INDEX, // You can later change this based on orientation
-        },
-        Position { x, y },
-        Wall,
-    ));
-}
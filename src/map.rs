@@ -1,5 +1,8 @@
+use bevy::prelude::*;
 use rand::Rng;
 
+use crate::components::Position;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Rect {
     pub x: i32,
@@ -118,3 +121,212 @@ pub fn bsp_split(rect: Rect, depth: u32, rng: &mut impl Rng) -> Vec<Room> {
         })
         .collect()
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WallMaterial {
+    Dirt,
+    Stone,
+    Igneous,
+    Catacomb,
+}
+
+impl WallMaterial {
+    pub const ALL: [WallMaterial; 4] = [
+        WallMaterial::Dirt,
+        WallMaterial::Stone,
+        WallMaterial::Igneous,
+        WallMaterial::Catacomb,
+    ];
+
+    /// Dig effort needed to break through one tile of this material.
+    pub fn hardness(self) -> u32 {
+        match self {
+            WallMaterial::Dirt => 2,
+            WallMaterial::Catacomb => 3,
+            WallMaterial::Stone => 4,
+            WallMaterial::Igneous => 6,
+        }
+    }
+
+    /// Sprite in `tiles.png` for a wall seen from above.
+    pub fn top_index(self) -> usize {
+        match self {
+            WallMaterial::Dirt => 0,
+            WallMaterial::Stone => 17,
+            WallMaterial::Igneous => 51,
+            WallMaterial::Catacomb => 85,
+        }
+    }
+
+    /// Sprite in `tiles.png` for a wall face with open floor in front of it.
+    pub fn side_index(self) -> usize {
+        self.top_index() + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Void,
+    Floor,
+    Wall(WallMaterial),
+    Door { open: bool },
+    StairsDown,
+}
+
+/// Sent whenever a tile in the [`DungeonMap`] changes after the level was generated.
+#[derive(Event)]
+pub struct TileChanged {
+    pub pos: Position,
+}
+
+/// How much the player knows about a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileVisibility {
+    Unseen,
+    Remembered,
+    Visible,
+}
+
+/// Sent when a tile enters or leaves the player's field of view.
+#[derive(Event)]
+pub struct TileVisibilityChanged {
+    pub pos: Position,
+}
+
+/// The authoritative tile grid for the current level.
+#[derive(Resource)]
+pub struct DungeonMap {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<Tile>,
+    rooms: Vec<Option<usize>>,
+    dig_progress: Vec<u32>,
+    visible: Vec<bool>,
+    revealed: Vec<bool>,
+}
+
+impl DungeonMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        let len = (width * height) as usize;
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::Void; len],
+            rooms: vec![None; len],
+            dig_progress: vec![0; len],
+            visible: vec![false; len],
+            revealed: vec![false; len],
+        }
+    }
+
+    pub fn in_bounds(&self, pos: Position) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+
+    fn idx(&self, pos: Position) -> Option<usize> {
+        self.in_bounds(pos)
+            .then(|| (pos.y * self.width + pos.x) as usize)
+    }
+
+    pub fn tile(&self, pos: Position) -> Tile {
+        self.idx(pos).map_or(Tile::Void, |i| self.tiles[i])
+    }
+
+    pub fn set_tile(&mut self, pos: Position, tile: Tile) {
+        if let Some(i) = self.idx(pos) {
+            self.tiles[i] = tile;
+            self.dig_progress[i] = 0;
+        }
+    }
+
+    pub fn room_at(&self, pos: Position) -> Option<usize> {
+        self.idx(pos).and_then(|i| self.rooms[i])
+    }
+
+    pub fn set_room(&mut self, pos: Position, room: usize) {
+        if let Some(i) = self.idx(pos) {
+            self.rooms[i] = Some(room);
+        }
+    }
+
+    /// Open ground of any kind, including doors whether open or shut.
+    pub fn is_floor(&self, pos: Position) -> bool {
+        matches!(self.tile(pos), Tile::Floor | Tile::Door { .. } | Tile::StairsDown)
+    }
+
+    pub fn is_walkable(&self, pos: Position) -> bool {
+        self.is_floor(pos) && self.tile(pos) != Tile::Door { open: false }
+    }
+
+    pub fn blocks_sight(&self, pos: Position) -> bool {
+        !self.is_walkable(pos)
+    }
+
+    /// Whether the player can currently see this tile.
+    pub fn is_visible(&self, pos: Position) -> bool {
+        self.idx(pos).is_some_and(|i| self.visible[i])
+    }
+
+    /// Whether the player has ever seen this tile.
+    pub fn is_revealed(&self, pos: Position) -> bool {
+        self.idx(pos).is_some_and(|i| self.revealed[i])
+    }
+
+    pub fn set_visible(&mut self, pos: Position, visible: bool) {
+        if let Some(i) = self.idx(pos) {
+            self.visible[i] = visible;
+            self.revealed[i] |= visible;
+        }
+    }
+
+    pub fn visibility(&self, pos: Position) -> TileVisibility {
+        if self.is_visible(pos) {
+            TileVisibility::Visible
+        } else if self.is_revealed(pos) {
+            TileVisibility::Remembered
+        } else {
+            TileVisibility::Unseen
+        }
+    }
+
+    /// Walls on the outer edge of the map are bedrock and can never be removed.
+    pub fn is_diggable(&self, pos: Position) -> bool {
+        matches!(self.tile(pos), Tile::Wall(_))
+            && pos.x > 0
+            && pos.y > 0
+            && pos.x < self.width - 1
+            && pos.y < self.height - 1
+    }
+
+    /// Adds `power` dig effort to a wall and returns true once it gives way.
+    pub fn apply_dig(&mut self, pos: Position, power: u32) -> bool {
+        let Tile::Wall(material) = self.tile(pos) else {
+            return false;
+        };
+        if !self.is_diggable(pos) {
+            return false;
+        }
+        let Some(i) = self.idx(pos) else {
+            return false;
+        };
+        self.dig_progress[i] += power;
+        self.dig_progress[i] >= material.hardness()
+    }
+
+    /// Top or side sprite depending on whether floor lies directly below.
+    pub fn wall_sprite_index(&self, pos: Position) -> Option<usize> {
+        let Tile::Wall(material) = self.tile(pos) else {
+            return None;
+        };
+        let below = Position { x: pos.x, y: pos.y - 1 };
+        Some(if self.is_floor(below) {
+            material.side_index()
+        } else {
+            material.top_index()
+        })
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| Position { x, y }))
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{AppState, PlayerClass, SelectedClass};
+use crate::{stairs::Depth, AppState, PlayerClass, SelectedClass};
 
 const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
@@ -13,7 +13,10 @@ impl Plugin for MenuPlugin {
         app.init_state::<AppState>()
             .add_systems(OnEnter(AppState::Menu), setup_menu)
             .add_systems(Update, menu.run_if(in_state(AppState::Menu)))
-            .add_systems(OnExit(AppState::Menu), cleanup_menu);
+            .add_systems(OnExit(AppState::Menu), cleanup_menu)
+            .add_systems(OnEnter(AppState::GameOver), setup_game_over)
+            .add_systems(Update, game_over.run_if(in_state(AppState::GameOver)))
+            .add_systems(OnExit(AppState::GameOver), cleanup_game_over);
     }
 }
 
@@ -22,6 +25,11 @@ struct MenuData {
     root_entity: Entity,
 }
 
+#[derive(Resource)]
+struct GameOverData {
+    root_entity: Entity,
+}
+
 fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
 
@@ -76,20 +84,19 @@ fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(MenuData { root_entity });
 }
 
+type ButtonInteraction = (Changed<Interaction>, With<Button>);
+
 fn menu(
     mut next_state: ResMut<NextState<AppState>>,
     mut selected_class: ResMut<SelectedClass>,
-    mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor, &PlayerClass),
-        (Changed<Interaction>, With<Button>),
-    >,
+    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, &PlayerClass), ButtonInteraction>,
 ) {
     for (interaction, mut color, class) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 *color = PRESSED_BUTTON.into();
                 selected_class.0 = Some(*class);
-                next_state.set(AppState::InGame);
+                next_state.set(AppState::Loading);
             }
             Interaction::Hovered => {
                 *color = HOVERED_BUTTON.into();
@@ -104,3 +111,53 @@ fn menu(
 fn cleanup_menu(mut commands: Commands, menu_data: Res<MenuData>) {
     commands.entity(menu_data.root_entity).despawn_recursive();
 }
+
+fn setup_game_over(mut commands: Commands, asset_server: Res<AssetServer>, depth: Res<Depth>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    let root_entity = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.),
+                ..default()
+            },
+            background_color: Color::srgba(0., 0., 0., 0.6).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!("You died on depth {}.", depth.0),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 40.,
+                    color: Color::WHITE,
+                },
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Enter to return to the menu",
+                TextStyle {
+                    font,
+                    font_size: 24.,
+                    color: Color::WHITE,
+                },
+            ));
+        })
+        .id();
+
+    commands.insert_resource(GameOverData { root_entity });
+}
+
+fn game_over(keyboard_input: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn cleanup_game_over(mut commands: Commands, game_over_data: Res<GameOverData>) {
+    commands.entity(game_over_data.root_entity).despawn_recursive();
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use bevy::{
     color::palettes::css,
     prelude::*,
@@ -5,42 +7,47 @@ use bevy::{
 };
 
 use crate::{
-    components::{MinimapTile, Player, Position, RoomId},
-    map::Room,
+    components::{LevelEntity, MinimapTile, Player, Position, RoomId},
+    map::{DungeonMap, TileChanged, TileVisibilityChanged},
     AppState, MAP_HEIGHT, MAP_WIDTH, MINIMAP_LAYER,
 };
 
-/// Plugin that handles minimap tile rendering and real-time room highlighting.
+/// Side length in pixels of one minimap cell.
+pub const MINIMAP_TILE_SIZE: f32 = 4.0;
+
+/// Plugin that handles minimap tile rendering, fog of war and real-time room highlighting.
 pub struct MinimapPlugin;
 
 impl Plugin for MinimapPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            update_minimap_highlight.run_if(in_state(AppState::InGame)),
+            (
+                reveal_dug_minimap_tiles,
+                reveal_explored_minimap_tiles,
+                update_minimap_highlight,
+            )
+                .run_if(in_state(AppState::InGame)),
         );
     }
 }
 
+#[derive(Component)]
+pub struct MinimapRoot;
+
 fn update_minimap_highlight(
     player_query: Query<&Position, With<Player>>,
-    floor_query: Query<(&Position, &RoomId)>,
-    mut minimap_tiles: Query<(&RoomId, &mut BackgroundColor), With<MinimapTile>>,
+    map: Res<DungeonMap>,
+    mut minimap_tiles: Query<(Option<&RoomId>, &mut BackgroundColor), With<MinimapTile>>,
 ) {
     let Ok(player_pos) = player_query.get_single() else { return };
 
     // Determine current room based on player position
-    let mut current_room_id = None;
-    for (pos, room_id) in floor_query.iter() {
-        if pos == player_pos {
-            current_room_id = Some(*room_id);
-            break;
-        }
-    }
+    let current_room_id = map.room_at(*player_pos).map(RoomId);
 
     // Highlight UI tiles
     for (room_id, mut bg_color) in &mut minimap_tiles {
-        bg_color.0 = if Some(*room_id) == current_room_id {
+        bg_color.0 = if room_id.is_some() && room_id.copied() == current_room_id {
             css::YELLOW.into()
         } else {
             css::DARK_GRAY.into()
@@ -48,12 +55,8 @@ fn update_minimap_highlight(
     }
 }
 
-pub fn spawn_minimap_ui_tiles(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    rooms: &[Room],
-) {
-    let tile_size = 4.0;
+pub fn spawn_minimap_ui_tiles(commands: &mut Commands, map: &DungeonMap) {
+    let tile_size = MINIMAP_TILE_SIZE;
 
     let container = commands
         .spawn(NodeBundle {
@@ -69,31 +72,79 @@ pub fn spawn_minimap_ui_tiles(
             background_color: BackgroundColor(Color::NONE),
             ..default()
         })
+        .insert((MinimapRoot, LevelEntity))
         .id();
 
-    for room in rooms {
-        for y in room.inner.y..room.inner.y + room.inner.height {
-            for x in room.inner.x..room.inner.x + room.inner.width {
-                commands.entity(container).with_children(|parent| {
-                    parent.spawn((
-                        ImageBundle {
-                            style: Style {
-                                position_type: PositionType::Absolute,
-                                left: Val::Px(x as f32 * tile_size),
-                                top: Val::Px((MAP_HEIGHT as i32 - 1 - y) as f32 * tile_size),
-                                width: Val::Px(tile_size),
-                                height: Val::Px(tile_size),
-                                ..default()
-                            },
-                            background_color: css::DARK_GRAY.into(),
-                            ..default()
-                        },
-                        RoomId(room.id),
-                        MinimapTile,
-                        Position { x, y },
-                    ));
-                });
-            }
+    commands.entity(container).with_children(|parent| {
+        for pos in map.positions().filter(|&p| map.is_floor(p)) {
+            spawn_minimap_cell(parent, map, pos);
+        }
+    });
+}
+
+/// One minimap cell, hidden until the player has explored that tile.
+fn spawn_minimap_cell(parent: &mut ChildBuilder, map: &DungeonMap, pos: Position) {
+    let tile_size = MINIMAP_TILE_SIZE;
+    let mut cell = parent.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(pos.x as f32 * tile_size),
+                top: Val::Px((MAP_HEIGHT as i32 - 1 - pos.y) as f32 * tile_size),
+                width: Val::Px(tile_size),
+                height: Val::Px(tile_size),
+                ..default()
+            },
+            background_color: css::DARK_GRAY.into(),
+            visibility: if map.is_revealed(pos) {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            },
+            ..default()
+        },
+        MinimapTile,
+        pos,
+    ));
+    if let Some(room) = map.room_at(pos) {
+        cell.insert(RoomId(room));
+    }
+}
+
+/// Adds minimap cells for floor opened up by digging after the level was generated.
+fn reveal_dug_minimap_tiles(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    mut changed_events: EventReader<TileChanged>,
+    root_query: Query<Entity, With<MinimapRoot>>,
+    minimap_tiles: Query<&Position, With<MinimapTile>>,
+) {
+    let Ok(root) = root_query.get_single() else { return };
+
+    for TileChanged { pos } in changed_events.read() {
+        if !map.is_floor(*pos) || minimap_tiles.iter().any(|p| p == pos) {
+            continue;
+        }
+        commands.entity(root).with_children(|parent| {
+            spawn_minimap_cell(parent, &map, *pos);
+        });
+    }
+}
+
+/// Shows minimap cells once the tiles behind them have been seen.
+fn reveal_explored_minimap_tiles(
+    map: Res<DungeonMap>,
+    mut visibility_events: EventReader<TileVisibilityChanged>,
+    mut minimap_tiles: Query<(&Position, &mut Visibility), With<MinimapTile>>,
+) {
+    let changed: HashSet<Position> = visibility_events.read().map(|e| e.pos).collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    for (pos, mut visibility) in &mut minimap_tiles {
+        if changed.contains(pos) && map.is_revealed(*pos) {
+            visibility.set_if_neq(Visibility::Inherited);
         }
     }
 }
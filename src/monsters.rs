@@ -0,0 +1,199 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    ai::{AiProfile, AiState},
+    combat::{AttackProfile, MeleeAttack, RangedAttack, Resistances},
+    components::{Digger, Enemy, Energy, Health, LevelEntity, LightSource, Mana, Position, RenderLayer, Viewshed},
+    data::{PendingData, RonAssetLoader},
+    dice::Dice,
+    grid::{ATLAS_CELL_SIZE, TILE_SIZE},
+    pathfinding::MovementType,
+    spells::{SpellRegistry, Spellbook},
+    stats::{StatModifiers, Stats, MANA_PER_MAGIC},
+};
+
+/// Chance that a monster is asleep when the level is generated.
+const SLEEP_CHANCE: f64 = 0.5;
+
+/// Plugin for the monster definitions in `assets/monsters.ron`.
+pub struct MonstersPlugin;
+
+impl Plugin for MonstersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MonsterRegistry>()
+            .register_asset_loader(RonAssetLoader::<MonsterRegistry>::new(&["monsters.ron"]))
+            .add_systems(Startup, load_monsters);
+    }
+}
+
+fn default_vision() -> i32 {
+    6
+}
+
+/// One kind of monster, as described in `monsters.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonsterDef {
+    pub id: String,
+    /// Shown in the log as "the <name>".
+    pub name: String,
+    /// Sprite in `monsters.png`.
+    pub sprite: usize,
+    pub stats: Stats,
+    pub melee: AttackProfile,
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+    #[serde(default)]
+    pub ai: AiProfile,
+    #[serde(default)]
+    pub movement: MovementType,
+    #[serde(default)]
+    pub resistances: Resistances,
+    /// Ids of the spells it knows, from `spells.ron`.
+    #[serde(default)]
+    pub spells: Vec<String>,
+    #[serde(default)]
+    pub loot: Vec<LootDrop>,
+    /// Shallowest and deepest levels it turns up on.
+    pub depth: (u32, u32),
+    /// What it costs out of a level's encounter budget.
+    pub difficulty: u32,
+    #[serde(default = "default_vision")]
+    pub vision: i32,
+    /// Light it carries or gives off, if any.
+    #[serde(default)]
+    pub light: Option<LightSource>,
+}
+
+impl MonsterDef {
+    pub fn found_at(&self, depth: u32) -> bool {
+        (self.depth.0..=self.depth.1).contains(&depth)
+    }
+}
+
+/// Something a monster may leave behind when it dies.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LootDrop {
+    pub chance: f64,
+    pub item: LootItem,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum LootItem {
+    Gold(Dice),
+    /// A book teaching the spell with this id.
+    Book(String),
+    /// A scroll teaching the spell with this id.
+    Scroll(String),
+}
+
+/// What a creature drops on death.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Loot(pub Vec<LootDrop>);
+
+/// Every monster in the game, as read from `monsters.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct MonsterRegistry {
+    pub monsters: Vec<MonsterDef>,
+}
+
+impl MonsterRegistry {
+    pub fn find(&self, id: &str) -> Option<&MonsterDef> {
+        self.monsters.iter().find(|monster| monster.id == id)
+    }
+}
+
+#[derive(Resource)]
+pub struct MonsterRegistryHandle(pub Handle<MonsterRegistry>);
+
+/// Read access to the loaded monster registry.
+#[derive(SystemParam)]
+pub struct Monsters<'w> {
+    registries: Res<'w, Assets<MonsterRegistry>>,
+    handle: Res<'w, MonsterRegistryHandle>,
+}
+
+impl Monsters<'_> {
+    /// Only call once the game has started: `AppState::Loading` waits for `monsters.ron`.
+    pub fn registry(&self) -> &MonsterRegistry {
+        self.registries
+            .get(&self.handle.0)
+            .expect("monsters.ron is loaded before the game starts")
+    }
+}
+
+fn load_monsters(mut commands: Commands, asset_server: Res<AssetServer>, mut pending: ResMut<PendingData>) {
+    let handle = asset_server.load("monsters.ron");
+    pending.0.push(handle.clone().untyped());
+    commands.insert_resource(MonsterRegistryHandle(handle));
+}
+
+/// Spawns a monster on the current level.
+pub fn spawn_monster(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    (def, spells): (&MonsterDef, &SpellRegistry),
+    pos: Position,
+    rng: &mut impl Rng,
+) -> Entity {
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 12, 13, None, None);
+    let mut monster = commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("monsters.png"),
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_translation(pos.to_world(RenderLayer::Creature.z())),
+            ..default()
+        },
+        TextureAtlas {
+            layout: texture_atlas_layouts.add(layout),
+            index: def.sprite,
+        },
+        pos,
+        RenderLayer::Creature,
+        Enemy,
+        Name::new(format!("the {}", def.name)),
+        LevelEntity,
+        (
+            Health(def.stats.max_hp),
+            def.stats,
+            StatModifiers::default(),
+            MeleeAttack(def.melee),
+            def.resistances.clone(),
+            Loot(def.loot.clone()),
+        ),
+        (
+            def.ai,
+            def.movement,
+            if rng.gen_bool(SLEEP_CHANCE) {
+                AiState::Sleeping
+            } else {
+                AiState::Wandering { goal: None }
+            },
+        ),
+        Viewshed {
+            range: def.vision,
+            ..default()
+        },
+        Energy(0),
+    ));
+    if let Some(ranged) = def.ranged {
+        monster.insert(ranged);
+    }
+    if def.movement == MovementType::Tunnel {
+        monster.insert(Digger { power: 1 });
+    }
+    if let Some(light) = def.light {
+        monster.insert(light);
+    }
+    if !def.spells.is_empty() {
+        let known = def.spells.iter().filter_map(|id| spells.find(id)).collect();
+        let mana = def.stats.magic * MANA_PER_MAGIC;
+        monster.insert((Spellbook(known), Mana { current: mana, max: mana }));
+    }
+    monster.id()
+}
@@ -0,0 +1,455 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    actions::ActionSet,
+    components::{Player, Position},
+    items::Item,
+    map::{DungeonMap, Tile},
+    AppState,
+};
+
+/// Orthogonal steps, the only moves creatures make.
+pub const STEPS: [IVec2; 4] = [IVec2::Y, IVec2::NEG_Y, IVec2::NEG_X, IVec2::X];
+
+/// Scale applied to distances when turning a map inside out for fleeing, as a fraction.
+/// Above 1 it prefers long escape routes over corners that are merely far away.
+const FLEE_WEIGHT: (i32, i32) = (6, 5);
+
+/// Plugin that keeps the shared Dijkstra maps around the player and toward items up to date.
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (update_player_maps, update_item_map)
+                .after(ActionSet)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// How a creature gets around, which decides what it can path through.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum MovementType {
+    /// Walks the floors and opens doors on the way.
+    #[default]
+    Walk,
+    /// Walks, and burrows slowly through any rock soft enough to dig.
+    Tunnel,
+}
+
+/// What entering each cell costs a creature: the tile's price for its movement type,
+/// unless an override says otherwise.
+pub struct PathCosts<'a> {
+    map: &'a DungeonMap,
+    movement: MovementType,
+    overrides: HashMap<Position, Option<u32>>,
+}
+
+impl<'a> PathCosts<'a> {
+    pub fn new(map: &'a DungeonMap, movement: MovementType) -> Self {
+        PathCosts {
+            map,
+            movement,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Prices cells such as traps or occupied tiles differently; `None` makes them impassable.
+    pub fn with_overrides(mut self, overrides: impl IntoIterator<Item = (Position, Option<u32>)>) -> Self {
+        self.overrides.extend(overrides);
+        self
+    }
+
+    /// `None` where the cell can't be entered at all.
+    pub fn cost(&self, pos: Position) -> Option<u32> {
+        if let Some(&cost) = self.overrides.get(&pos) {
+            return cost;
+        }
+        match (self.map.tile(pos), self.movement) {
+            // A turn to open it, then one to step through
+            (Tile::Door { open: false }, _) => Some(2),
+            _ if self.map.is_walkable(pos) => Some(1),
+            (_, MovementType::Tunnel) if self.map.is_diggable(pos) => Some(4),
+            _ => None,
+        }
+    }
+}
+
+fn manhattan(a: Position, b: Position) -> u32 {
+    a.x.abs_diff(b.x) + a.y.abs_diff(b.y)
+}
+
+/// Cheapest path from `from` to `to`, excluding `from` and ending on `to`.
+/// `cost` prices entering a cell, `None` where it can't be entered; the goal itself is always enterable.
+pub fn astar(from: Position, to: Position, cost: impl Fn(Position) -> Option<u32>) -> Option<Vec<Position>> {
+    if from == to {
+        return Some(Vec::new());
+    }
+    let mut open = BinaryHeap::from([Reverse((manhattan(from, to), 0, from.x, from.y))]);
+    let mut best: HashMap<Position, u32> = HashMap::from([(from, 0)]);
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+
+    while let Some(Reverse((_, spent, x, y))) = open.pop() {
+        let current = Position { x, y };
+        if current == to {
+            let mut path = vec![current];
+            while let Some(&previous) = came_from.get(path.last()?) {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if best.get(&current).is_some_and(|&b| b < spent) {
+            continue;
+        }
+
+        for step in STEPS {
+            let next = current.offset(step);
+            let step_cost = if next == to { Some(1) } else { cost(next) };
+            let Some(step_cost) = step_cost else {
+                continue;
+            };
+            let spent = spent + step_cost.max(1);
+            if best.get(&next).is_some_and(|&b| b <= spent) {
+                continue;
+            }
+            best.insert(next, spent);
+            came_from.insert(next, current);
+            open.push(Reverse((spent + manhattan(next, to), spent, next.x, next.y)));
+        }
+    }
+    None
+}
+
+/// Cost of the cheapest walk from every cell to the nearest of a set of sources.
+#[derive(Debug, Clone)]
+pub struct DijkstraMap {
+    width: i32,
+    height: i32,
+    values: Vec<Option<i32>>,
+}
+
+impl DijkstraMap {
+    /// Spreads outward from every source at once, so each cell holds the cost to whichever is nearest.
+    pub fn new(
+        map: &DungeonMap,
+        sources: impl IntoIterator<Item = Position>,
+        cost: impl Fn(Position) -> Option<u32>,
+    ) -> Self {
+        Self::from_seeds(map, sources.into_iter().map(|pos| (pos, 0)), cost)
+    }
+
+    fn from_seeds(
+        map: &DungeonMap,
+        seeds: impl IntoIterator<Item = (Position, i32)>,
+        cost: impl Fn(Position) -> Option<u32>,
+    ) -> Self {
+        let mut dijkstra = DijkstraMap {
+            width: map.width,
+            height: map.height,
+            values: vec![None; (map.width * map.height) as usize],
+        };
+        let mut open = BinaryHeap::new();
+        for (pos, value) in seeds {
+            if dijkstra.set_if_lower(pos, value) {
+                open.push(Reverse((value, pos.x, pos.y)));
+            }
+        }
+
+        while let Some(Reverse((value, x, y))) = open.pop() {
+            let current = Position { x, y };
+            if dijkstra.value(current).is_some_and(|v| v < value) {
+                continue;
+            }
+            for step in STEPS {
+                let next = current.offset(step);
+                let Some(step_cost) = cost(next) else {
+                    continue;
+                };
+                let value = value + step_cost.max(1) as i32;
+                if dijkstra.set_if_lower(next, value) {
+                    open.push(Reverse((value, next.x, next.y)));
+                }
+            }
+        }
+        dijkstra
+    }
+
+    fn index(&self, pos: Position) -> Option<usize> {
+        (pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height)
+            .then(|| (pos.y * self.width + pos.x) as usize)
+    }
+
+    fn set_if_lower(&mut self, pos: Position, value: i32) -> bool {
+        let Some(i) = self.index(pos) else {
+            return false;
+        };
+        if self.values[i].is_some_and(|v| v <= value) {
+            return false;
+        }
+        self.values[i] = Some(value);
+        true
+    }
+
+    /// `None` where no source can be reached.
+    pub fn value(&self, pos: Position) -> Option<i32> {
+        self.index(pos).and_then(|i| self.values[i])
+    }
+
+    /// A map that leads away from the sources: rolling downhill on it means getting away,
+    /// preferring escape routes that keep going over dead ends that are merely far.
+    pub fn fleeing(&self, map: &DungeonMap, cost: impl Fn(Position) -> Option<u32>) -> Self {
+        let (num, den) = FLEE_WEIGHT;
+        let seeds = map
+            .positions()
+            .filter_map(|pos| self.value(pos).map(|value| (pos, -value * num / den)));
+        Self::from_seeds(map, seeds, cost)
+    }
+
+    /// The step from `from` that lowers the value the most, if any does.
+    pub fn downhill(&self, from: Position) -> Option<IVec2> {
+        let here = self.value(from)?;
+        STEPS
+            .into_iter()
+            .filter_map(|step| self.value(from.offset(step)).map(|value| (step, value)))
+            .filter(|(_, value)| *value < here)
+            .min_by_key(|(_, value)| *value)
+            .map(|(step, _)| step)
+    }
+
+    /// Every cell's downhill step, worked out once for everyone following this map.
+    pub fn flow_field(&self, map: &DungeonMap) -> FlowField {
+        FlowField {
+            width: self.width,
+            directions: map.positions().map(|pos| self.downhill(pos)).collect(),
+        }
+    }
+}
+
+/// The step to take from each cell to follow a Dijkstra map downhill.
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    width: i32,
+    directions: Vec<Option<IVec2>>,
+}
+
+impl FlowField {
+    pub fn direction(&self, pos: Position) -> Option<IVec2> {
+        if pos.x < 0 || pos.y < 0 || pos.x >= self.width {
+            return None;
+        }
+        self.directions.get((pos.y * self.width + pos.x) as usize).copied().flatten()
+    }
+}
+
+/// Flow fields toward and away from the player for walking creatures, shared by every monster.
+#[derive(Resource)]
+pub struct PlayerMaps {
+    pub toward: FlowField,
+    pub away: FlowField,
+}
+
+fn update_player_maps(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    player_query: Query<Ref<Position>, With<Player>>,
+    player_maps: Option<Res<PlayerMaps>>,
+) {
+    let Ok(player_pos) = player_query.get_single() else {
+        return;
+    };
+    if player_maps.is_some() && !player_pos.is_changed() && !map.is_changed() {
+        return;
+    }
+
+    let costs = PathCosts::new(&map, MovementType::Walk);
+    let toward = DijkstraMap::new(&map, [*player_pos], |p| costs.cost(p));
+    let away = toward.fleeing(&map, |p| costs.cost(p));
+    commands.insert_resource(PlayerMaps {
+        toward: toward.flow_field(&map),
+        away: away.flow_field(&map),
+    });
+}
+
+/// Flow field toward the nearest item lying on the floor, for walking creatures.
+#[derive(Resource)]
+pub struct ItemMap {
+    pub toward: FlowField,
+}
+
+impl ItemMap {
+    pub fn new(map: &DungeonMap, items: impl IntoIterator<Item = Position>) -> Self {
+        let costs = PathCosts::new(map, MovementType::Walk);
+        let toward = DijkstraMap::new(map, items, |p| costs.cost(p));
+        ItemMap {
+            toward: toward.flow_field(map),
+        }
+    }
+}
+
+fn update_item_map(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    item_query: Query<&Position, With<Item>>,
+    added_query: Query<(), Added<Item>>,
+    mut removed: RemovedComponents<Item>,
+    item_map: Option<Res<ItemMap>>,
+) {
+    let items_changed = !added_query.is_empty() || removed.read().count() > 0;
+    if item_map.is_some() && !items_changed && !map.is_changed() {
+        return;
+    }
+    commands.insert_resource(ItemMap::new(&map, item_query.iter().copied()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::WallMaterial;
+
+    const WALL: Tile = Tile::Wall(WallMaterial::Stone);
+
+    /// A room of `width` by `height` floor cells inside a ring of wall.
+    fn room(width: i32, height: i32) -> DungeonMap {
+        let mut map = DungeonMap::new(width + 2, height + 2);
+        for pos in map.positions().collect::<Vec<_>>() {
+            let edge = pos.x == 0 || pos.y == 0 || pos.x == width + 1 || pos.y == height + 1;
+            map.set_tile(pos, if edge { WALL } else { Tile::Floor });
+        }
+        map
+    }
+
+    fn at(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn astar_finds_shortest_path() {
+        let map = room(5, 3);
+        let costs = PathCosts::new(&map, MovementType::Walk);
+        let path = astar(at(1, 1), at(5, 3), |p| costs.cost(p)).unwrap();
+
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.last(), Some(&at(5, 3)));
+        let mut previous = at(1, 1);
+        for &step in &path {
+            assert_eq!(manhattan(previous, step), 1);
+            assert!(map.is_walkable(step));
+            previous = step;
+        }
+    }
+
+    #[test]
+    fn astar_gives_up_when_walled_off() {
+        let mut map = room(5, 3);
+        for y in 1..=3 {
+            map.set_tile(at(3, y), WALL);
+        }
+        let costs = PathCosts::new(&map, MovementType::Walk);
+        assert_eq!(astar(at(1, 2), at(5, 2), |p| costs.cost(p)), None);
+    }
+
+    #[test]
+    fn astar_always_enters_the_goal() {
+        let map = room(5, 1);
+        // The goal is taken, as when walking up to attack someone
+        let costs = PathCosts::new(&map, MovementType::Walk).with_overrides([(at(5, 1), None)]);
+        let path = astar(at(1, 1), at(5, 1), |p| costs.cost(p)).unwrap();
+        assert_eq!(path, vec![at(2, 1), at(3, 1), at(4, 1), at(5, 1)]);
+    }
+
+    #[test]
+    fn dijkstra_measures_to_the_nearest_source() {
+        let map = room(5, 3);
+        let costs = PathCosts::new(&map, MovementType::Walk);
+        let dijkstra = DijkstraMap::new(&map, [at(1, 1), at(5, 3)], |p| costs.cost(p));
+
+        assert_eq!(dijkstra.value(at(1, 1)), Some(0));
+        assert_eq!(dijkstra.value(at(5, 3)), Some(0));
+        assert_eq!(dijkstra.value(at(2, 1)), Some(1));
+        assert_eq!(dijkstra.value(at(4, 3)), Some(1));
+        assert_eq!(dijkstra.value(at(3, 2)), Some(3));
+        assert_eq!(dijkstra.value(at(0, 0)), None);
+    }
+
+    #[test]
+    fn fleeing_leads_uphill_from_the_player() {
+        let map = room(7, 1);
+        let costs = PathCosts::new(&map, MovementType::Walk);
+        let toward = DijkstraMap::new(&map, [at(2, 1)], |p| costs.cost(p));
+        let away = toward.fleeing(&map, |p| costs.cost(p));
+
+        assert_eq!(away.downhill(at(3, 1)), Some(IVec2::X));
+        assert!(away.value(at(7, 1)) < away.value(at(1, 1)));
+    }
+
+    #[test]
+    fn flow_field_follows_the_map_downhill() {
+        let map = room(7, 1);
+        let costs = PathCosts::new(&map, MovementType::Walk);
+        let toward = DijkstraMap::new(&map, [at(2, 1)], |p| costs.cost(p));
+        let flow = toward.flow_field(&map);
+
+        assert_eq!(toward.downhill(at(5, 1)), Some(IVec2::NEG_X));
+        assert_eq!(flow.direction(at(5, 1)), Some(IVec2::NEG_X));
+        assert_eq!(flow.direction(at(1, 1)), Some(IVec2::X));
+        // Nowhere lower to go from the source itself
+        assert_eq!(flow.direction(at(2, 1)), None);
+        assert_eq!(flow.direction(at(-1, 1)), None);
+    }
+
+    #[test]
+    fn overrides_reprice_or_block_cells() {
+        let map = room(5, 1);
+        let trap = PathCosts::new(&map, MovementType::Walk).with_overrides([(at(3, 1), Some(5))]);
+        assert_eq!(trap.cost(at(3, 1)), Some(5));
+        assert_eq!(trap.cost(at(2, 1)), Some(1));
+
+        let blocked = PathCosts::new(&map, MovementType::Walk).with_overrides([(at(3, 1), None)]);
+        assert_eq!(blocked.cost(at(3, 1)), None);
+        assert_eq!(astar(at(1, 1), at(5, 1), |p| blocked.cost(p)), None);
+    }
+
+    #[test]
+    fn doors_and_digging_cost_extra() {
+        let mut map = room(5, 3);
+        map.set_tile(at(2, 2), Tile::Door { open: false });
+        map.set_tile(at(4, 2), WALL);
+        let walk = PathCosts::new(&map, MovementType::Walk);
+        let tunnel = PathCosts::new(&map, MovementType::Tunnel);
+
+        assert_eq!(walk.cost(at(2, 2)), Some(2));
+        assert_eq!(walk.cost(at(4, 2)), None);
+        assert_eq!(tunnel.cost(at(4, 2)), Some(4));
+        // Bedrock around the edge stays out of reach even for diggers
+        assert_eq!(tunnel.cost(at(0, 2)), None);
+    }
+
+    #[test]
+    fn item_map_leads_to_the_nearest_item() {
+        let mut map = room(7, 3);
+        for y in 1..=2 {
+            map.set_tile(at(5, y), WALL);
+        }
+        let items = ItemMap::new(&map, [at(1, 1), at(7, 1)]);
+
+        assert_eq!(items.toward.direction(at(3, 1)), Some(IVec2::NEG_X));
+        assert_eq!(items.toward.direction(at(6, 1)), Some(IVec2::X));
+        assert_eq!(items.toward.direction(at(1, 1)), None);
+
+        // With only the far item left, the way there goes round the wall
+        let items = ItemMap::new(&map, [at(7, 1)]);
+        assert_eq!(items.toward.direction(at(4, 1)), Some(IVec2::Y));
+    }
+}
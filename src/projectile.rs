@@ -0,0 +1,271 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    f32::consts::FRAC_PI_4,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    actions::ActionSet,
+    combat::{roll_attack, AttackProfile, Combat, CombatResult, DamageType, Resistances},
+    components::{AwareOfPlayer, BlocksSight, Enemy, Health, LevelEntity, LightSource, Position, RenderLayer, TakingTurn},
+    dice::Dice,
+    fov::has_line_of_sight,
+    grid::{line_between, ATLAS_CELL_SIZE, TILE_SIZE},
+    hud::MessageLog,
+    lighting::BaseColor,
+    map::DungeonMap,
+    stats::{DerivedStats, Stats},
+    AppState, ARROW_INDEX, SMALL_FIRE_INDEX,
+};
+
+/// Seconds a projectile takes to cross one cell.
+const STEP_SECONDS: f32 = 0.03;
+
+/// Plugin that flies projectiles cell by cell and resolves what they hit.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            fly_projectiles
+                .after(ActionSet)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectileKind {
+    Arrow,
+    /// A magical bolt, tinted by the kind of damage it carries.
+    Bolt(DamageType),
+}
+
+/// Something in flight; it holds `TakingTurn` so the world waits for it to land.
+#[derive(Component)]
+pub struct Projectile {
+    pub owner: Entity,
+    pub owner_is_player: bool,
+    pub attacker_stats: Stats,
+    pub attack: AttackProfile,
+    /// Cells still to fly through, nearest first.
+    pub path: VecDeque<Position>,
+    /// Radius of the burst where it lands; 0 strikes only the creature it hits.
+    pub burst_radius: i32,
+    /// Healing the owner gets for every creature it hurts.
+    pub drain: Option<Dice>,
+    timer: Timer,
+}
+
+impl Projectile {
+    pub fn new(
+        (owner, owner_is_player): (Entity, bool),
+        attacker_stats: Stats,
+        attack: AttackProfile,
+        path: Vec<Position>,
+        burst_radius: i32,
+    ) -> Self {
+        Projectile {
+            owner,
+            owner_is_player,
+            attacker_stats,
+            attack,
+            path: path.into(),
+            burst_radius,
+            drain: None,
+            timer: Timer::from_seconds(STEP_SECONDS, TimerMode::Repeating),
+        }
+    }
+
+    pub fn draining(self, drain: Option<Dice>) -> Self {
+        Projectile { drain, ..self }
+    }
+}
+
+/// Cells a shot from `origin` at `aim` passes through, up to `range` cells away;
+/// unless `stop_at_aim` it keeps flying past the aimed cell.
+pub fn flight_path(origin: Position, aim: Position, range: i32, stop_at_aim: bool) -> Vec<Position> {
+    let distance = (aim.x - origin.x).abs().max((aim.y - origin.y).abs());
+    if distance == 0 {
+        return Vec::new();
+    }
+    let end = if stop_at_aim {
+        aim
+    } else {
+        let scale = (range + distance - 1) / distance;
+        origin.offset(IVec2::new(aim.x - origin.x, aim.y - origin.y) * scale)
+    };
+    line_between(origin, end)
+        .into_iter()
+        .skip(1)
+        .take(range.max(0) as usize)
+        .collect()
+}
+
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    kind: ProjectileKind,
+    origin: Position,
+    projectile: Projectile,
+) {
+    let (texture, layout, index, color) = match kind {
+        ProjectileKind::Arrow => (
+            asset_server.load("items.png"),
+            TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 11, 26, None, None),
+            ARROW_INDEX,
+            Color::WHITE,
+        ),
+        ProjectileKind::Bolt(damage_type) => (
+            asset_server.load("animated-tiles.png"),
+            TextureAtlasLayout::from_grid(UVec2::splat(ATLAS_CELL_SIZE), 11, 12, None, None),
+            SMALL_FIRE_INDEX,
+            bolt_color(damage_type),
+        ),
+    };
+
+    // Arrow sprites point up and to the right
+    let heading = projectile
+        .path
+        .back()
+        .map_or(Vec2::X, |end| Vec2::new((end.x - origin.x) as f32, (end.y - origin.y) as f32));
+    let rotation = match kind {
+        ProjectileKind::Arrow => Quat::from_rotation_z(heading.to_angle() - FRAC_PI_4),
+        ProjectileKind::Bolt(_) => Quat::IDENTITY,
+    };
+
+    let mut entity = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..default()
+            },
+            texture,
+            transform: Transform::from_translation(origin.to_world(RenderLayer::Effect.z())).with_rotation(rotation),
+            ..default()
+        },
+        TextureAtlas {
+            layout: texture_atlas_layouts.add(layout),
+            index,
+        },
+        origin,
+        RenderLayer::Effect,
+        projectile,
+        TakingTurn,
+        BaseColor(color),
+        LevelEntity,
+    ));
+    // Magic lights up the cells it flies through
+    if let ProjectileKind::Bolt(_) = kind {
+        entity.insert(LightSource {
+            radius: 2,
+            intensity: 0.6,
+        });
+    }
+}
+
+/// Tint over the flame sprite; fire keeps its natural colours.
+fn bolt_color(damage_type: DamageType) -> Color {
+    match damage_type {
+        DamageType::Cold => Color::srgb(0.5, 0.8, 1.0),
+        DamageType::Poison => Color::srgb(0.5, 1.0, 0.4),
+        DamageType::Holy => Color::srgb(1.0, 1.0, 0.7),
+        DamageType::Unholy => Color::srgb(0.7, 0.4, 1.0),
+        _ => Color::WHITE,
+    }
+}
+
+type CreatureData<'a> = (
+    Entity,
+    &'a Position,
+    &'a DerivedStats,
+    Option<&'a Resistances>,
+    Has<Enemy>,
+    Has<AwareOfPlayer>,
+);
+
+/// Steps every projectile along its path; it stops at a wall or the first creature and hits on arrival.
+#[allow(clippy::too_many_arguments)]
+fn fly_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    combat: Combat,
+    map: Res<DungeonMap>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Position)>,
+    creature_query: Query<CreatureData, (With<Health>, Without<Projectile>)>,
+    blocker_query: Query<&Position, (With<BlocksSight>, Without<Projectile>)>,
+    mut owner_query: Query<(&mut Health, &DerivedStats), Without<Projectile>>,
+    mut result_events: EventWriter<CombatResult>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+
+    for (entity, mut projectile, mut pos) in &mut projectile_query {
+        if !projectile.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        let owner = projectile.owner;
+        let creature_at = |cell: Position| {
+            creature_query
+                .iter()
+                .any(|(creature, creature_pos, ..)| *creature_pos == cell && creature != owner)
+        };
+        let landed = match projectile.path.pop_front() {
+            Some(next) if !map.is_walkable(next) => true,
+            Some(next) => {
+                *pos = next;
+                creature_at(next) || projectile.path.is_empty()
+            }
+            None => true,
+        };
+        if !landed {
+            continue;
+        }
+
+        // A burst only reaches creatures in plain view of where it goes off
+        let mut struck = false;
+        let mut drained = 0;
+        for (creature, creature_pos, DerivedStats(target_stats), resistances, is_enemy, aware) in &creature_query {
+            let distance = (creature_pos.x - pos.x).abs().max((creature_pos.y - pos.y).abs());
+            if creature == projectile.owner
+                || distance > projectile.burst_radius
+                || !has_line_of_sight(&map, &blockers, *pos, *creature_pos)
+            {
+                continue;
+            }
+            let result = roll_attack(
+                &mut rng,
+                combat.rules(),
+                (projectile.owner, &projectile.attacker_stats),
+                (creature, target_stats, resistances),
+                projectile.attack,
+                projectile.owner_is_player && is_enemy && !aware,
+            );
+            if result.damage > 0
+                && let Some(drain) = projectile.drain
+            {
+                drained += drain.roll(&mut rng).total().max(0);
+            }
+            result_events.send(result);
+            struck = true;
+        }
+        if drained > 0
+            && let Ok((mut health, DerivedStats(stats))) = owner_query.get_mut(projectile.owner)
+        {
+            let healed = drained.min(stats.max_hp - health.0);
+            health.0 += healed;
+            if projectile.owner_is_player && healed > 0 {
+                log.push(format!("You drain {healed} HP."));
+            }
+        }
+        if !struck && projectile.owner_is_player && projectile.burst_radius == 0 {
+            log.push("Your shot hits nothing.");
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
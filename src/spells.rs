@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    combat::{capitalize, roll_attack, AttackProfile, Combat, CombatResult, Resistances},
+    components::{AwareOfPlayer, BlocksSight, Enemy, Energy, Health, Mana, Player, PlayersTurn, Position, TakingTurn},
+    data::{PendingData, RonAssetLoader},
+    dice::Dice,
+    fov::has_line_of_sight,
+    hud::MessageLog,
+    map::DungeonMap,
+    projectile::{flight_path, spawn_projectile, Projectile, ProjectileKind},
+    stats::{DerivedStats, Stats},
+    targeting::{target_problem, TargetPurpose, Targeting},
+    AppState, PlayerClass,
+};
+
+/// Hotkeys for the spellbook, in the order the spells were learned.
+pub const SPELL_KEYS: [KeyCode; 7] = [
+    KeyCode::KeyQ,
+    KeyCode::KeyW,
+    KeyCode::KeyE,
+    KeyCode::KeyR,
+    KeyCode::KeyT,
+    KeyCode::KeyY,
+    KeyCode::KeyU,
+];
+
+/// Mana regained at the start of each of the caster's turns.
+const MANA_REGEN: i32 = 1;
+
+/// Plugin for the spell registry in `assets/spells.ron`, mana and casting.
+pub struct SpellsPlugin;
+
+impl Plugin for SpellsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SpellRegistry>()
+            .register_asset_loader(RonAssetLoader::<SpellRegistry>::new(&["spells.ron"]))
+            .add_systems(Startup, load_spells)
+            .add_systems(
+                Update,
+                (
+                    (
+                        regenerate_mana,
+                        spell_input.run_if(not(resource_exists::<Targeting>)),
+                    )
+                        .chain()
+                        .before(ActionSet),
+                    resolve_casts.in_set(ActionSet),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Index of a spell in the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpellId(pub usize);
+
+/// Where a spell goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SpellShape {
+    /// Flies at the target and strikes the first creature in its way.
+    Bolt,
+    /// Flies to the aimed cell and bursts there.
+    Ball { radius: i32 },
+    /// Bursts around the caster, sparing the caster.
+    Burst { radius: i32 },
+    /// Acts on the caster alone.
+    Caster,
+    /// Acts on the aimed cell without crossing the space between.
+    Cell,
+}
+
+impl SpellShape {
+    pub fn is_aimed(self) -> bool {
+        matches!(self, SpellShape::Bolt | SpellShape::Ball { .. } | SpellShape::Cell)
+    }
+
+    /// Radius of the area around the target that gets hit.
+    pub fn radius(self) -> i32 {
+        match self {
+            SpellShape::Ball { radius } | SpellShape::Burst { radius } => radius,
+            SpellShape::Bolt | SpellShape::Caster | SpellShape::Cell => 0,
+        }
+    }
+}
+
+/// What a spell does to its caster besides any damage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SpellEffect {
+    Heal(Dice),
+    /// Heals the caster for every creature the spell's damage lands on.
+    Drain(Dice),
+    /// Moves the caster to the aimed cell.
+    Teleport,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpellDef {
+    pub id: String,
+    pub name: String,
+    pub cost: i32,
+    #[serde(default)]
+    pub range: i32,
+    pub shape: SpellShape,
+    #[serde(default)]
+    pub damage: Option<AttackProfile>,
+    #[serde(default)]
+    pub effects: Vec<SpellEffect>,
+    /// Sprite in `items.png` for the book that teaches it.
+    pub icon: usize,
+    /// Classes that know the spell from the start.
+    #[serde(default)]
+    pub starting_classes: Vec<PlayerClass>,
+}
+
+impl SpellDef {
+    pub fn drain(&self) -> Option<Dice> {
+        self.effects.iter().find_map(|effect| match effect {
+            SpellEffect::Drain(dice) => Some(*dice),
+            _ => None,
+        })
+    }
+}
+
+/// Every spell in the game, as read from `spells.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct SpellRegistry {
+    pub spells: Vec<SpellDef>,
+}
+
+impl SpellRegistry {
+    pub fn get(&self, id: SpellId) -> Option<&SpellDef> {
+        self.spells.get(id.0)
+    }
+
+    pub fn find(&self, id: &str) -> Option<SpellId> {
+        self.spells.iter().position(|spell| spell.id == id).map(SpellId)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = SpellId> {
+        (0..self.spells.len()).map(SpellId)
+    }
+
+    pub fn starting_spells(&self, class: PlayerClass) -> Vec<SpellId> {
+        self.ids()
+            .filter(|id| self.spells[id.0].starting_classes.contains(&class))
+            .collect()
+    }
+}
+
+#[derive(Resource)]
+pub struct SpellRegistryHandle(pub Handle<SpellRegistry>);
+
+/// Read access to the loaded spell registry.
+#[derive(SystemParam)]
+pub struct Spells<'w> {
+    registries: Res<'w, Assets<SpellRegistry>>,
+    handle: Res<'w, SpellRegistryHandle>,
+}
+
+impl Spells<'_> {
+    /// Only call once the game has started: `AppState::Loading` waits for `spells.ron`.
+    pub fn registry(&self) -> &SpellRegistry {
+        self.registries
+            .get(&self.handle.0)
+            .expect("spells.ron is loaded before the game starts")
+    }
+
+    pub fn get(&self, id: SpellId) -> Option<&SpellDef> {
+        self.registry().get(id)
+    }
+}
+
+/// The spells an actor knows, bound to the spell keys in order.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Spellbook(pub Vec<SpellId>);
+
+fn load_spells(mut commands: Commands, asset_server: Res<AssetServer>, mut pending: ResMut<PendingData>) {
+    let handle = asset_server.load("spells.ron");
+    pending.0.push(handle.clone().untyped());
+    commands.insert_resource(SpellRegistryHandle(handle));
+}
+
+fn regenerate_mana(mut query: Query<&mut Mana, Added<TakingTurn>>) {
+    for mut mana in &mut query {
+        mana.current = (mana.current + MANA_REGEN).min(mana.max);
+    }
+}
+
+/// Aimed spells open the targeting cursor; the rest are cast on the spot.
+fn spell_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    spells: Spells,
+    player_query: Query<(Entity, &Position, &Spellbook, &Mana), PlayersTurn>,
+    mut action_events: EventWriter<ActionEvent>,
+    mut log: ResMut<MessageLog>,
+) {
+    let Ok((player, pos, spellbook, mana)) = player_query.get_single() else {
+        return;
+    };
+
+    for (key, &id) in SPELL_KEYS.iter().zip(&spellbook.0) {
+        if !keyboard_input.just_pressed(*key) {
+            continue;
+        }
+        let Some(spell) = spells.get(id) else {
+            continue;
+        };
+        if mana.current < spell.cost {
+            log.push(format!("You need {} mana to cast {}.", spell.cost, spell.name));
+            continue;
+        }
+        if spell.shape.is_aimed() {
+            commands.insert_resource(Targeting {
+                purpose: TargetPurpose::Spell(id),
+                cursor: *pos,
+                range: spell.range,
+                radius: spell.shape.radius(),
+            });
+        } else {
+            action_events.send(ActionEvent {
+                actor: player,
+                action: GameAction::Cast(id, None),
+            });
+        }
+    }
+}
+
+type CasterData<'a> = (
+    &'a Position,
+    &'a DerivedStats,
+    &'a mut Mana,
+    &'a Spellbook,
+    &'a mut Health,
+    &'a mut Energy,
+    Option<&'a Name>,
+    Has<Player>,
+);
+
+type TargetData<'a> = (
+    Entity,
+    &'a Position,
+    &'a DerivedStats,
+    Option<&'a Resistances>,
+    Has<Enemy>,
+    Has<AwareOfPlayer>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn resolve_casts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    (combat, map, spells): (Combat, Res<DungeonMap>, Spells),
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    mut action_events: EventReader<ActionEvent>,
+    mut caster_query: Query<CasterData>,
+    target_query: Query<TargetData, With<Health>>,
+    mut result_events: EventWriter<CombatResult>,
+    mut log: ResMut<MessageLog>,
+) {
+    let mut rng = rand::thread_rng();
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+
+    for event in action_events.read() {
+        let GameAction::Cast(id, target) = event.action else {
+            continue;
+        };
+        let caster = event.actor;
+        let Ok((pos, DerivedStats(stats), mut mana, spellbook, mut health, mut energy, name, is_player)) =
+            caster_query.get_mut(caster)
+        else {
+            continue;
+        };
+        let origin = *pos;
+        let mut fail = |message: String| {
+            if is_player {
+                log.push(message);
+            }
+        };
+
+        let Some(spell) = spells.get(id).filter(|_| spellbook.0.contains(&id)) else {
+            fail("You don't know that spell.".to_string());
+            settle(&mut commands, caster, &mut energy, is_player, false);
+            continue;
+        };
+        if mana.current < spell.cost {
+            fail(format!("You need {} mana to cast {}.", spell.cost, spell.name));
+            settle(&mut commands, caster, &mut energy, is_player, false);
+            continue;
+        }
+        let aim = match (spell.shape.is_aimed(), target) {
+            (false, _) => Some(origin),
+            (true, Some(target)) => match target_problem((&map, &blockers), origin, target, spell.range) {
+                Some(problem) => {
+                    fail(problem.to_string());
+                    None
+                }
+                None => Some(target),
+            },
+            (true, None) => {
+                fail("Pick a target.".to_string());
+                None
+            }
+        };
+        let Some(aim) = aim else {
+            settle(&mut commands, caster, &mut energy, is_player, false);
+            continue;
+        };
+        let teleports = spell.effects.contains(&SpellEffect::Teleport);
+        let occupied = target_query.iter().any(|(_, p, ..)| *p == aim);
+        if teleports && (!map.is_walkable(aim) || occupied) {
+            fail("You can't go there.".to_string());
+            settle(&mut commands, caster, &mut energy, is_player, false);
+            continue;
+        }
+
+        mana.current -= spell.cost;
+        log.push(match (is_player, name) {
+            (true, _) => format!("You cast {}.", spell.name),
+            (false, Some(name)) => format!("{} casts {}.", capitalize(name.as_str()), spell.name),
+            (false, None) => format!("Something casts {}.", spell.name),
+        });
+
+        // Spell damage scales with magic rather than strength
+        let casting = Stats {
+            attack: stats.magic,
+            ..*stats
+        };
+        let mut drained = 0;
+        if let Some(damage) = spell.damage {
+            match spell.shape {
+                SpellShape::Bolt | SpellShape::Ball { .. } => spawn_projectile(
+                    &mut commands,
+                    &asset_server,
+                    &mut texture_atlas_layouts,
+                    ProjectileKind::Bolt(damage.damage_type),
+                    origin,
+                    Projectile::new(
+                        (caster, is_player),
+                        casting,
+                        damage,
+                        flight_path(origin, aim, spell.range, spell.shape != SpellShape::Bolt),
+                        spell.shape.radius(),
+                    )
+                    .draining(spell.drain()),
+                ),
+                // Like a projectile's burst, the blast only reaches creatures in plain view of its centre
+                SpellShape::Burst { .. } | SpellShape::Cell | SpellShape::Caster => {
+                    let radius = spell.shape.radius();
+                    for (target, target_pos, DerivedStats(target_stats), resistances, is_enemy, aware) in &target_query {
+                        let distance = (target_pos.x - aim.x).abs().max((target_pos.y - aim.y).abs());
+                        if target == caster
+                            || distance > radius
+                            || !has_line_of_sight(&map, &blockers, aim, *target_pos)
+                        {
+                            continue;
+                        }
+                        let result = roll_attack(
+                            &mut rng,
+                            combat.rules(),
+                            (caster, &casting),
+                            (target, target_stats, resistances),
+                            damage,
+                            is_player && is_enemy && !aware,
+                        );
+                        if result.damage > 0
+                            && let Some(drain) = spell.drain()
+                        {
+                            drained += drain.roll(&mut rng).total().max(0);
+                        }
+                        result_events.send(result);
+                    }
+                }
+            }
+        }
+
+        for effect in &spell.effects {
+            match effect {
+                SpellEffect::Heal(dice) => {
+                    let healed = dice.roll(&mut rng).total().max(0).min(stats.max_hp - health.0);
+                    health.0 += healed;
+                    if is_player {
+                        log.push(format!("You recover {healed} HP."));
+                    }
+                }
+                SpellEffect::Drain(_) => {
+                    let healed = drained.min(stats.max_hp - health.0);
+                    health.0 += healed;
+                    if is_player && healed > 0 {
+                        log.push(format!("You drain {healed} HP."));
+                    }
+                }
+                SpellEffect::Teleport => {
+                    commands.entity(caster).insert(aim);
+                }
+            }
+        }
+        settle(&mut commands, caster, &mut energy, is_player, true);
+    }
+}
@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{settle, ActionEvent, ActionSet, GameAction},
+    components::{Energy, LevelEntity, Player, Position},
+    encounters::Encounters,
+    game::spawn_level,
+    map::{DungeonMap, Room, Tile},
+    monsters::Monsters,
+    spells::Spells,
+    AppState,
+};
+
+/// Plugin for the stairs down and the fresh level waiting at the bottom.
+pub struct StairsPlugin;
+
+impl Plugin for StairsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DescendEvent>().add_systems(
+            Update,
+            (
+                resolve_descend.in_set(ActionSet),
+                descend_to_next_level.after(ActionSet),
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// How many staircases the player has taken, starting at 1.
+#[derive(Resource)]
+pub struct Depth(pub u32);
+
+/// The player took the stairs down.
+#[derive(Event)]
+pub struct DescendEvent;
+
+/// Puts the stairs down in the middle of the last room.
+pub fn place_stairs(map: &mut DungeonMap, rooms: &[Room]) {
+    if let Some(last) = rooms.last() {
+        let (x, y) = last.inner.center();
+        map.set_tile(Position { x, y }, Tile::StairsDown);
+    }
+}
+
+fn resolve_descend(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    mut action_events: EventReader<ActionEvent>,
+    mut actor_query: Query<(&Position, &mut Energy, Has<Player>)>,
+    mut descend_events: EventWriter<DescendEvent>,
+) {
+    for event in action_events.read() {
+        if event.action != GameAction::Descend {
+            continue;
+        }
+        let Ok((pos, mut energy, is_player)) = actor_query.get_mut(event.actor) else {
+            continue;
+        };
+
+        // Monsters stay on their own level
+        let succeeded = is_player && map.tile(*pos) == Tile::StairsDown;
+        if succeeded {
+            descend_events.send(DescendEvent);
+        }
+        settle(&mut commands, event.actor, &mut energy, is_player, succeeded);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn descend_to_next_level(
+    mut commands: Commands,
+    mut descend_events: EventReader<DescendEvent>,
+    depth: Res<Depth>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    level_query: Query<Entity, With<LevelEntity>>,
+    mut player_query: Query<&mut Position, With<Player>>,
+    (spells, monsters, encounters): (Spells, Monsters, Encounters),
+) {
+    if descend_events.read().count() == 0 {
+        return;
+    }
+
+    for entity in &level_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    // Inserted alongside the new map, so whatever watches the depth sees both change together
+    let depth = depth.0 + 1;
+    commands.insert_resource(Depth(depth));
+
+    let start = spawn_level(
+        &mut commands,
+        &asset_server,
+        &mut texture_atlas_layouts,
+        depth,
+        (spells.registry(), monsters.registry(), encounters.table()),
+    );
+    if let Ok(mut pos) = player_query.get_single_mut() {
+        *pos = start;
+    }
+}
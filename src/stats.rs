@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    components::{Health, Mana, Speed, TakingTurn},
+    turn::{TurnSet, NORMAL_SPEED},
+    AppState, PlayerClass,
+};
+
+/// Spell points granted by each point of magic.
+pub const MANA_PER_MAGIC: i32 = 4;
+
+/// Plugin that folds stat modifiers into every creature's derived stats and wears off timed ones.
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (derive_stats.before(TurnSet), wear_off_modifiers.after(TurnSet))
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// A creature's base stats; see `DerivedStats` for the values combat actually uses.
+/// Data files may leave out any stat but `max_hp`; speed then defaults to normal.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct Stats {
+    pub max_hp: i32,
+    #[serde(default)]
+    pub attack: i32,
+    #[serde(default)]
+    pub defense: i32,
+    #[serde(default)]
+    pub accuracy: i32,
+    #[serde(default)]
+    pub evasion: i32,
+    #[serde(default = "normal_speed")]
+    pub speed: i32,
+    #[serde(default)]
+    pub magic: i32,
+}
+
+fn normal_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+impl Stats {
+    pub fn for_class(class: PlayerClass) -> Self {
+        match class {
+            PlayerClass::Warrior => Stats {
+                max_hp: 30,
+                attack: 2,
+                defense: 2,
+                accuracy: 2,
+                evasion: 0,
+                speed: NORMAL_SPEED,
+                magic: 0,
+            },
+            PlayerClass::Mage => Stats {
+                max_hp: 18,
+                attack: 0,
+                defense: 0,
+                accuracy: 1,
+                evasion: 1,
+                speed: NORMAL_SPEED,
+                magic: 6,
+            },
+            PlayerClass::Ranger => Stats {
+                max_hp: 24,
+                attack: 1,
+                defense: 1,
+                accuracy: 4,
+                evasion: 3,
+                speed: NORMAL_SPEED + 2,
+                magic: 1,
+            },
+        }
+    }
+
+    /// These stats with every modifier applied.
+    pub fn with_modifiers<'a>(mut self, modifiers: impl IntoIterator<Item = &'a StatModifier>) -> Self {
+        for StatModifier { bonus, .. } in modifiers {
+            self.max_hp += bonus.max_hp;
+            self.attack += bonus.attack;
+            self.defense += bonus.defense;
+            self.accuracy += bonus.accuracy;
+            self.evasion += bonus.evasion;
+            self.speed += bonus.speed;
+            self.magic += bonus.magic;
+        }
+        self.max_hp = self.max_hp.max(1);
+        self.speed = self.speed.max(1);
+        self
+    }
+}
+
+/// A bonus or penalty to a creature's stats, from a worn item or a status effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatModifier {
+    /// Added to the base stats; anything left at zero is unaffected.
+    pub bonus: Stats,
+    /// The creature's own turns left before it wears off; `None` lasts until removed.
+    pub turns: Option<u32>,
+}
+
+/// Everything currently modifying a creature's stats.
+#[derive(Component, Default)]
+pub struct StatModifiers(pub Vec<StatModifier>);
+
+/// Base stats plus modifiers, kept up to date by `derive_stats`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DerivedStats(pub Stats);
+
+type StatsData<'a> = (Entity, &'a Stats, Option<&'a StatModifiers>, Option<&'a mut Health>, Option<&'a mut Mana>);
+type StatsChanged = Or<(Changed<Stats>, Changed<StatModifiers>)>;
+
+fn derive_stats(
+    mut commands: Commands,
+    mut query: Query<StatsData, StatsChanged>,
+) {
+    for (entity, stats, modifiers, health, mana) in &mut query {
+        let derived = stats.with_modifiers(modifiers.iter().flat_map(|m| &m.0));
+        if let Some(mut health) = health {
+            health.0 = health.0.min(derived.max_hp);
+        }
+        if let Some(mut mana) = mana {
+            mana.max = derived.magic * MANA_PER_MAGIC;
+            mana.current = mana.current.min(mana.max);
+        }
+        commands
+            .entity(entity)
+            .insert((DerivedStats(derived), Speed(derived.speed)));
+    }
+}
+
+/// Timed modifiers count down at the start of each of the owner's turns.
+fn wear_off_modifiers(mut query: Query<&mut StatModifiers, Added<TakingTurn>>) {
+    for mut modifiers in &mut query {
+        if modifiers.0.iter().all(|modifier| modifier.turns.is_none()) {
+            continue;
+        }
+        modifiers.0.retain_mut(|modifier| match &mut modifier.turns {
+            Some(turns) => {
+                *turns = turns.saturating_sub(1);
+                *turns > 0
+            }
+            None => true,
+        });
+    }
+}
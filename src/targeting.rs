@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    abilities::Ability,
+    actions::{ActionEvent, ActionSet, GameAction},
+    combat::RangedAttack,
+    components::{BlocksSight, CameraFollow, Enemy, Player, PlayersTurn, Position, RenderLayer},
+    fov::has_line_of_sight,
+    grid::{line_between, world_to_grid, TILE_SIZE},
+    hud::MessageLog,
+    map::DungeonMap,
+    spells::SpellId,
+    AppState,
+};
+
+const PATH_COLOR: Color = Color::srgba(1.0, 1.0, 0.4, 0.3);
+const AREA_COLOR: Color = Color::srgba(1.0, 0.5, 0.1, 0.35);
+const VALID_CURSOR_COLOR: Color = Color::srgba(0.3, 1.0, 0.3, 0.5);
+const INVALID_CURSOR_COLOR: Color = Color::srgba(1.0, 0.2, 0.2, 0.5);
+
+/// Plugin for the targeting cursor used to aim ranged attacks and abilities.
+pub struct TargetingPlugin;
+
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), cancel_targeting)
+            .add_systems(
+                Update,
+                (
+                    begin_shooting.run_if(not(resource_exists::<Targeting>)),
+                    targeting_input.run_if(resource_exists::<Targeting>),
+                    draw_targeting_preview,
+                )
+                    .chain()
+                    .before(ActionSet)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// What the player is aiming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetPurpose {
+    Shoot,
+    Ability(Ability),
+    Spell(SpellId),
+}
+
+/// Present while the player is picking a target; normal input is suspended until it is confirmed or cancelled.
+#[derive(Resource, Debug, Clone)]
+pub struct Targeting {
+    pub purpose: TargetPurpose,
+    pub cursor: Position,
+    pub range: i32,
+    /// Radius of the area around the cursor that gets hit, 0 for a single cell.
+    pub radius: i32,
+}
+
+#[derive(Component)]
+struct TargetingMarker;
+
+fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs())
+}
+
+/// Why `target` can't be aimed at from `origin`, if it can't; `blockers` are the things standing in the way of sight.
+pub fn target_problem(
+    (map, blockers): (&DungeonMap, &HashSet<Position>),
+    origin: Position,
+    target: Position,
+    range: i32,
+) -> Option<&'static str> {
+    if target == origin {
+        Some("Pick a target.")
+    } else if distance(origin, target) > range {
+        Some("That is out of range.")
+    } else if !map.is_visible(target) {
+        Some("You can't see there.")
+    } else if !has_line_of_sight(map, blockers, origin, target) {
+        Some("Something is in the way.")
+    } else {
+        None
+    }
+}
+
+fn cancel_targeting(mut commands: Commands) {
+    commands.remove_resource::<Targeting>();
+}
+
+fn begin_shooting(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player_query: Query<(&Position, Option<&RangedAttack>), PlayersTurn>,
+    mut log: ResMut<MessageLog>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    let Ok((pos, ranged)) = player_query.get_single() else {
+        return;
+    };
+    let Some(ranged) = ranged else {
+        log.push("You have nothing to shoot with.");
+        return;
+    };
+
+    commands.insert_resource(Targeting {
+        purpose: TargetPurpose::Shoot,
+        cursor: *pos,
+        range: ranged.range,
+        radius: 0,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn targeting_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut cursor_events: EventReader<CursorMoved>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CameraFollow>>,
+    map: Res<DungeonMap>,
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    mut targeting: ResMut<Targeting>,
+    player_query: Query<(Entity, &Position), PlayersTurn>,
+    enemy_query: Query<&Position, With<Enemy>>,
+    mut action_events: EventWriter<ActionEvent>,
+    mut log: ResMut<MessageLog>,
+) {
+    let Ok((player, origin)) = player_query.get_single() else {
+        return;
+    };
+
+    // Visible monsters nearest first, for snapping and cycling
+    let mut candidates: Vec<Position> = enemy_query
+        .iter()
+        .copied()
+        .filter(|p| map.is_visible(*p) && distance(*origin, *p) <= targeting.range)
+        .collect();
+    candidates.sort_by_key(|p| distance(*origin, *p));
+
+    if targeting.is_added() {
+        log.push("Aim with Tab, arrows or the mouse; Enter or click to confirm, Esc to cancel.");
+        if let Some(&nearest) = candidates.first() {
+            targeting.cursor = nearest;
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<Targeting>();
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Tab) && !candidates.is_empty() {
+        let next = candidates
+            .iter()
+            .position(|p| *p == targeting.cursor)
+            .map_or(0, |i| (i + 1) % candidates.len());
+        targeting.cursor = candidates[next];
+    }
+
+    for (key, dir) in [
+        (KeyCode::ArrowUp, IVec2::Y),
+        (KeyCode::ArrowDown, IVec2::NEG_Y),
+        (KeyCode::ArrowLeft, IVec2::NEG_X),
+        (KeyCode::ArrowRight, IVec2::X),
+    ] {
+        if keyboard_input.just_pressed(key) {
+            targeting.cursor = targeting.cursor.offset(dir);
+        }
+    }
+
+    if let Some(moved) = cursor_events.read().last()
+        && let Ok((camera, camera_transform)) = camera_query.get_single()
+        && let Some(world) = camera.viewport_to_world_2d(camera_transform, moved.position)
+    {
+        let hovered = world_to_grid(world);
+        if map.in_bounds(hovered) && targeting.cursor != hovered {
+            targeting.cursor = hovered;
+        }
+    }
+
+    if !keyboard_input.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter])
+        && !mouse_input.just_pressed(MouseButton::Left)
+    {
+        return;
+    }
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+    if let Some(problem) = target_problem((&map, &blockers), *origin, targeting.cursor, targeting.range) {
+        log.push(problem);
+        return;
+    }
+
+    let action = match targeting.purpose {
+        TargetPurpose::Shoot => GameAction::Shoot(targeting.cursor),
+        TargetPurpose::Ability(ability) => GameAction::UseAbility(ability, Some(targeting.cursor)),
+        TargetPurpose::Spell(spell) => GameAction::Cast(spell, Some(targeting.cursor)),
+    };
+    action_events.send(ActionEvent { actor: player, action });
+    commands.remove_resource::<Targeting>();
+}
+
+/// Shades the flight path, the area of effect and the cursor itself, red when the target is invalid.
+fn draw_targeting_preview(
+    mut commands: Commands,
+    targeting: Option<Res<Targeting>>,
+    map: Res<DungeonMap>,
+    blocker_query: Query<&Position, With<BlocksSight>>,
+    player_query: Query<&Position, With<Player>>,
+    marker_query: Query<Entity, With<TargetingMarker>>,
+) {
+    let changed = targeting.as_ref().map_or(!marker_query.is_empty(), |t| t.is_changed());
+    if !changed {
+        return;
+    }
+    for marker in &marker_query {
+        commands.entity(marker).despawn();
+    }
+    let (Some(targeting), Ok(origin)) = (targeting, player_query.get_single()) else {
+        return;
+    };
+
+    let mut marker = |pos: Position, color: Color| {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(pos.to_world(RenderLayer::Overlay.z())),
+                ..default()
+            },
+            TargetingMarker,
+        ));
+    };
+
+    let blockers: HashSet<Position> = blocker_query.iter().copied().collect();
+    let cursor = targeting.cursor;
+    for pos in line_between(*origin, cursor) {
+        if pos != *origin && pos != cursor {
+            marker(pos, PATH_COLOR);
+        }
+    }
+    for y in cursor.y - targeting.radius..=cursor.y + targeting.radius {
+        for x in cursor.x - targeting.radius..=cursor.x + targeting.radius {
+            let pos = Position { x, y };
+            if pos != cursor
+                && map.in_bounds(pos)
+                && !map.blocks_sight(pos)
+                && has_line_of_sight(&map, &blockers, cursor, pos)
+            {
+                marker(pos, AREA_COLOR);
+            }
+        }
+    }
+    let valid = target_problem((&map, &blockers), *origin, cursor, targeting.range).is_none();
+    marker(cursor, if valid { VALID_CURSOR_COLOR } else { INVALID_CURSOR_COLOR });
+}
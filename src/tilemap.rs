@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        primitives::Aabb,
+        render_asset::RenderAssetUsages,
+    },
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{
+    components::{LevelEntity, Position, RenderLayer},
+    grid::TILE_SIZE,
+    lighting::{LightMap, TileLightChanged},
+    map::{DungeonMap, Tile, TileChanged, TileVisibility, TileVisibilityChanged},
+    stairs::Depth,
+    AppState, DOOR_OPEN_INDEX, DOOR_SHUT_INDEX, FLOOR_TILE_INDEX, STAIRS_DOWN_INDEX,
+};
+
+/// Tiles per side of a render chunk.
+pub const CHUNK_SIZE: i32 = 16;
+
+const TILESET_COLUMNS: usize = 17;
+const TILESET_ROWS: usize = 26;
+
+/// Vertex tint for tiles the player remembers but cannot currently see.
+pub const REMEMBERED_TINT: [f32; 4] = [0.35, 0.35, 0.45, 1.0];
+
+/// Plugin that draws the explored part of the [`DungeonMap`] as one batched mesh per chunk,
+/// rebuilding only chunks whose tiles changed. Each new level gets a fresh set of chunks.
+pub struct TilemapPlugin;
+
+impl Plugin for TilemapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                spawn_tilemap_chunks.run_if(resource_exists_and_changed::<Depth>),
+                rebuild_changed_chunks,
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct TileChunk {
+    pub coord: IVec2,
+}
+
+fn chunk_of(pos: Position) -> IVec2 {
+    IVec2::new(pos.x.div_euclid(CHUNK_SIZE), pos.y.div_euclid(CHUNK_SIZE))
+}
+
+fn tile_sprite_index(map: &DungeonMap, pos: Position) -> Option<usize> {
+    match map.tile(pos) {
+        Tile::Void => None,
+        Tile::Floor => Some(FLOOR_TILE_INDEX),
+        Tile::Wall(_) => map.wall_sprite_index(pos),
+        Tile::Door { open: false } => Some(DOOR_SHUT_INDEX),
+        Tile::Door { open: true } => Some(DOOR_OPEN_INDEX),
+        Tile::StairsDown => Some(STAIRS_DOWN_INDEX),
+    }
+}
+
+fn build_chunk_mesh(map: &DungeonMap, light_map: &LightMap, coord: IVec2) -> Mesh {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for ly in 0..CHUNK_SIZE {
+        for lx in 0..CHUNK_SIZE {
+            let pos = Position {
+                x: coord.x * CHUNK_SIZE + lx,
+                y: coord.y * CHUNK_SIZE + ly,
+            };
+            let tint = match map.visibility(pos) {
+                TileVisibility::Unseen => continue,
+                TileVisibility::Remembered => REMEMBERED_TINT,
+                TileVisibility::Visible => {
+                    let shade = 0.2 + 0.8 * light_map.light_at(pos);
+                    [shade, shade * 0.95, shade * 0.85, 1.0]
+                }
+            };
+            let Some(index) = tile_sprite_index(map, pos) else {
+                continue;
+            };
+
+            let (x, y) = (lx as f32 * TILE_SIZE, ly as f32 * TILE_SIZE);
+            let half = TILE_SIZE / 2.0;
+            let col = (index % TILESET_COLUMNS) as f32;
+            let row = (index / TILESET_COLUMNS) as f32;
+            let (u0, u1) = (col / TILESET_COLUMNS as f32, (col + 1.0) / TILESET_COLUMNS as f32);
+            let (v0, v1) = (row / TILESET_ROWS as f32, (row + 1.0) / TILESET_ROWS as f32);
+
+            let base = positions.len() as u32;
+            positions.extend([
+                [x - half, y - half, 0.0],
+                [x + half, y - half, 0.0],
+                [x + half, y + half, 0.0],
+                [x - half, y + half, 0.0],
+            ]);
+            uvs.extend([[u0, v1], [u1, v1], [u1, v0], [u0, v0]]);
+            colors.extend([tint; 4]);
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+fn spawn_tilemap_chunks(
+    mut commands: Commands,
+    map: Res<DungeonMap>,
+    light_map: Res<LightMap>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let material = materials.add(ColorMaterial {
+        texture: Some(asset_server.load("tiles.png")),
+        ..default()
+    });
+
+    // Fixed bounds so frustum culling stays correct as chunk meshes are rebuilt
+    let extent = CHUNK_SIZE as f32 * TILE_SIZE;
+    let half = TILE_SIZE / 2.0;
+    let bounds = Aabb::from_min_max(Vec3::new(-half, -half, 0.0), Vec3::new(extent - half, extent - half, 0.0));
+
+    let chunks_x = (map.width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let chunks_y = (map.height + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    for cy in 0..chunks_y {
+        for cx in 0..chunks_x {
+            let coord = IVec2::new(cx, cy);
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(meshes.add(build_chunk_mesh(&map, &light_map, coord))),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(cx as f32 * extent, cy as f32 * extent, RenderLayer::Floor.z()),
+                    ..default()
+                },
+                bounds,
+                TileChunk { coord },
+                LevelEntity,
+            ));
+        }
+    }
+}
+
+fn rebuild_changed_chunks(
+    map: Res<DungeonMap>,
+    light_map: Res<LightMap>,
+    mut changed_events: EventReader<TileChanged>,
+    mut visibility_events: EventReader<TileVisibilityChanged>,
+    mut light_events: EventReader<TileLightChanged>,
+    chunk_query: Query<(&TileChunk, &Mesh2dHandle)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let mut dirty = HashSet::new();
+    for TileChanged { pos } in changed_events.read() {
+        // Walls pick their sprite from the tile below, so the row above may change too
+        dirty.insert(chunk_of(*pos));
+        dirty.insert(chunk_of(Position { x: pos.x, y: pos.y + 1 }));
+    }
+    for TileVisibilityChanged { pos } in visibility_events.read() {
+        dirty.insert(chunk_of(*pos));
+    }
+    for TileLightChanged { pos } in light_events.read() {
+        dirty.insert(chunk_of(*pos));
+    }
+    if dirty.is_empty() {
+        return;
+    }
+
+    for (chunk, mesh) in &chunk_query {
+        if dirty.contains(&chunk.coord) {
+            meshes.insert(mesh.0.id(), build_chunk_mesh(&map, &light_map, chunk.coord));
+        }
+    }
+}
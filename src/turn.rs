@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{Energy, Player, Speed, TakingTurn},
+    AppState,
+};
+
+/// Energy an actor spends on one action.
+pub const ACTION_COST: i32 = 100;
+
+/// Speed of an ordinary actor: one action per turn.
+pub const NORMAL_SPEED: i32 = 10;
+
+/// Safety limit so a level without ready actors cannot stall a frame.
+const MAX_TICKS_PER_FRAME: u32 = 1000;
+
+/// Plugin that hands out turns by energy, only letting the world advance once the player has acted.
+pub struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameTime>().add_systems(
+            Update,
+            schedule_turns
+                .in_set(TurnSet)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// Runs before any system that acts on `TakingTurn`.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TurnSet;
+
+/// Scheduler ticks elapsed on the current game; a turn is `ACTION_COST / NORMAL_SPEED` ticks.
+#[derive(Resource, Default)]
+pub struct GameTime {
+    pub ticks: u64,
+}
+
+impl GameTime {
+    pub fn turn(&self) -> u64 {
+        self.ticks / (ACTION_COST / NORMAL_SPEED) as u64
+    }
+}
+
+/// Spends the actor's energy and hands control back to the scheduler.
+pub fn end_turn(commands: &mut Commands, entity: Entity, energy: &mut Energy) {
+    energy.0 -= ACTION_COST;
+    commands.entity(entity).remove::<TakingTurn>();
+}
+
+fn schedule_turns(
+    mut commands: Commands,
+    mut time: ResMut<GameTime>,
+    mut actor_query: Query<(Entity, &mut Energy, &Speed, Has<Player>)>,
+    acting_query: Query<(), With<TakingTurn>>,
+) {
+    if !acting_query.is_empty() || !actor_query.iter().any(|(.., player)| player) {
+        return;
+    }
+
+    for _ in 0..MAX_TICKS_PER_FRAME {
+        let mut player_ready = None;
+        let mut monsters_ready = false;
+        for (entity, energy, _, player) in &actor_query {
+            if energy.0 < ACTION_COST {
+                continue;
+            }
+            if player {
+                player_ready = Some(entity);
+            } else {
+                monsters_ready = true;
+                commands.entity(entity).insert(TakingTurn);
+            }
+        }
+
+        // Monsters that are ready move first, then the world waits on the player
+        if monsters_ready {
+            return;
+        }
+        if let Some(player) = player_ready {
+            commands.entity(player).insert(TakingTurn);
+            return;
+        }
+
+        for (_, mut energy, speed, _) in &mut actor_query {
+            energy.0 += speed.0;
+        }
+        time.ticks += 1;
+    }
+}